@@ -0,0 +1,9 @@
+# Opens humsh and puts the command it builds on the command line instead of
+# running it. Load with `eval "$(humsh init bash)"` and bind a key, e.g.
+#   bind -x '"\eh": humsh-widget'
+humsh-widget() {
+  local cmd
+  cmd="$(humsh --emit 3 3>&1 >/dev/tty </dev/tty)"
+  READLINE_LINE="${READLINE_LINE:0:READLINE_POINT}$cmd${READLINE_LINE:READLINE_POINT}"
+  READLINE_POINT=$((READLINE_POINT + ${#cmd}))
+}
@@ -0,0 +1,8 @@
+# Opens humsh and puts the command it builds on the command line instead of
+# running it. Load with `humsh init fish | source` and bind a key, e.g.
+#   bind \eh humsh-widget
+function humsh-widget
+    set -l cmd (humsh --emit 3 --shell fish 3>&1 >/dev/tty </dev/tty | string collect)
+    commandline --insert -- $cmd
+    commandline -f repaint
+end
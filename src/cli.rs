@@ -0,0 +1,155 @@
+use std::path::PathBuf;
+
+use anyhow::{bail, Context as _};
+
+use crate::ui::Shell;
+
+pub const USAGE: &str = "\
+usage: humsh [options] [program]
+       humsh [options] check-config [--edit]
+       humsh init <zsh|bash|fish>
+
+Opens the home page, or `program` directly, which is `jj` or the name of a
+program definition.
+
+options:
+    --config <path>  use this file instead of the user config
+    -C <dir>         start in <dir>
+    --keys <keys>    press <keys> without a terminal and print the command
+                     they build, <esc> is the escape key
+    --exec           with --keys, run the command instead of printing it
+    --emit <fd>      exit and write the command to <fd> instead of running it,
+                     used by the widgets from `humsh init`
+    --shell <shell>  with --emit, write the command for zsh, bash or fish
+    --edit           with check-config, open the editor at the first problem
+    -V, --version    print the version
+    -h, --help       print this help
+";
+
+/// The command line of humsh itself.
+#[derive(Debug, Default)]
+pub struct Args {
+    /// Opened instead of the home page.
+    pub program: Option<String>,
+    pub check_config: bool,
+    /// Opens the first problem `check_config` finds in the editor.
+    pub edit: bool,
+    pub config: Option<PathBuf>,
+    pub dir: Option<PathBuf>,
+    /// Pressed without a terminal, see [`crate::ui::Ui::run_script`].
+    pub keys: Option<String>,
+    pub exec: bool,
+    pub emit: Option<u32>,
+    /// The shell `emit` writes for.
+    pub shell: Option<Shell>,
+    /// Prints the widget for this shell.
+    pub init: Option<String>,
+    pub version: bool,
+    pub help: bool,
+}
+
+impl Args {
+    pub fn parse(args: impl IntoIterator<Item = String>) -> anyhow::Result<Args> {
+        let mut parsed = Args::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            // `--config=path` is the same as `--config path`
+            let (name, inline_value) = match arg.split_once('=') {
+                Some((name, value)) if name.starts_with("--") => (name, Some(value.to_owned())),
+                _ => (arg.as_str(), None),
+            };
+            let mut value = || {
+                inline_value
+                    .clone()
+                    .or_else(|| args.next())
+                    .with_context(|| format!("`{name}` needs a value"))
+            };
+            match name {
+                "--config" => parsed.config = Some(value()?.into()),
+                "-C" => parsed.dir = Some(value()?.into()),
+                "--keys" => parsed.keys = Some(value()?),
+                "--exec" => parsed.exec = true,
+                "--edit" => parsed.edit = true,
+                "--emit" => {
+                    let fd = value()?;
+                    parsed.emit = Some(fd.parse().with_context(|| {
+                        format!("`--emit` needs a file descriptor, not `{fd}`")
+                    })?);
+                }
+                "--shell" => parsed.shell = Some(value()?.parse()?),
+                "-V" | "--version" => parsed.version = true,
+                "-h" | "--help" => parsed.help = true,
+                _ if name.starts_with('-') => {
+                    bail!("unknown option `{name}`, see `humsh --help`")
+                }
+                "check-config" if parsed.program.is_none() => parsed.check_config = true,
+                "init" if parsed.program.is_none() => parsed.init = Some(value()?),
+                _ if parsed.program.is_none() && !parsed.check_config && parsed.init.is_none() => {
+                    parsed.program = Some(arg)
+                }
+                _ => bail!("unexpected argument `{arg}`, see `humsh --help`"),
+            }
+        }
+        if parsed.exec && parsed.keys.is_none() {
+            bail!("`--exec` needs `--keys`");
+        }
+        if parsed.edit && !parsed.check_config {
+            bail!("`--edit` needs `check-config`");
+        }
+        if parsed.shell.is_some() && parsed.emit.is_none() {
+            bail!("`--shell` needs `--emit`");
+        }
+        if parsed.emit.is_some() && parsed.keys.is_some() {
+            bail!("`--emit` cannot be used with `--keys`");
+        }
+        Ok(parsed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> anyhow::Result<Args> {
+        Args::parse(args.iter().map(|x| x.to_string()))
+    }
+
+    #[test]
+    fn parses_program_and_options() {
+        let args = parse(&["--config=a.toml", "-C", "dir", "jj", "--keys", "pd"]).unwrap();
+        assert_eq!(args.program.as_deref(), Some("jj"));
+        assert_eq!(args.config, Some(PathBuf::from("a.toml")));
+        assert_eq!(args.dir, Some(PathBuf::from("dir")));
+        assert_eq!(args.keys.as_deref(), Some("pd"));
+        let args = parse(&["--emit", "3", "--shell", "fish"]).unwrap();
+        assert_eq!((args.emit, args.shell), (Some(3), Some(Shell::Fish)));
+    }
+
+    #[test]
+    fn parses_subcommands() {
+        let args = parse(&["check-config", "--edit"]).unwrap();
+        assert!(args.check_config && args.edit);
+        assert_eq!(args.program, None);
+        assert_eq!(
+            parse(&["init", "zsh"]).unwrap().init.as_deref(),
+            Some("zsh")
+        );
+    }
+
+    #[test]
+    fn rejects_bad_arguments() {
+        for args in [
+            &["--nope"][..],
+            &["jj", "extra"],
+            &["--keys"],
+            &["--exec"],
+            &["--edit"],
+            &["--emit", "x"],
+            &["--emit", "3", "--keys", "p"],
+            &["--shell", "fish"],
+            &["--emit", "3", "--shell", "nu"],
+        ] {
+            assert!(parse(args).is_err(), "{args:?}");
+        }
+    }
+}
@@ -1,4 +1,7 @@
 use std::collections::BTreeSet;
+use std::fmt;
+
+use crate::util::shell_quote;
 
 #[derive(Debug, Clone)]
 pub struct CommandLine {
@@ -46,11 +49,13 @@ impl ArgValue {
             }
         }
     }
+}
 
-    pub fn to_string(&self) -> String {
+impl fmt::Display for ArgValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let mut args = Vec::new();
         self.add_to(&mut args);
-        args.join(" ")
+        f.write_str(&args.join(" "))
     }
 }
 
@@ -81,12 +86,27 @@ impl CommandLine {
         cmd
     }
 
-    pub fn to_string(&self) -> String {
+    /// Like the `Display` impl, but quoted so a shell reads back the same
+    /// arguments.
+    pub fn to_shell(&self) -> String {
+        let mut args = Vec::new();
+        for arg in &self.args {
+            arg.value.add_to(&mut args);
+        }
+        args.iter()
+            .map(|x| shell_quote(x))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+impl fmt::Display for CommandLine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let mut args = Vec::new();
         for arg in &self.args {
             arg.value.add_to(&mut args);
         }
-        args.join(" ")
+        f.write_str(&args.join(" "))
     }
 }
 
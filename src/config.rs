@@ -1,49 +1,713 @@
-use std::{fs, path::Path};
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
 
-use crate::{data};
+use anyhow::{bail, Context as _};
+use toml::Spanned;
+
+use crate::command_line::{Arg, ArgOrder, ArgValue};
+use crate::data::{self, ShellContext};
+use crate::ui::{Context, Theme};
+use crate::util::shell_quote;
+
+pub use check::{check_keys, Diagnostic, KeyEntry, Level, Location};
+pub use keys::KeyMap;
+pub use program::ProgramDef;
+
+use check::Source;
+
+mod check;
+mod keys;
+mod placeholder;
+mod program;
 
 #[derive(Debug, Default, Clone, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct Config {
+    /// Configs whose commands are added to this one, relative to this file.
+    #[serde(default)]
+    include: Vec<Spanned<String>>,
     #[serde(default)]
     pub commands: Vec<Command>,
     pub git: Option<bool>,
     pub cp: Option<bool>,
+    /// Only read from the user config.
+    pub theme: Option<Theme>,
+    /// Only read from the user config.
+    #[serde(default)]
+    pub keys: KeyMap,
+    /// Editor command, e.g. `nvim` or `code --wait`, used instead of
+    /// `$VISUAL` and `$EDITOR`. Only read from the user config.
+    pub editor: Option<String>,
+    /// Keeps what command lines write to stderr for the error panel. Their
+    /// stderr is a pipe then instead of the terminal. Only read from the user
+    /// config.
+    pub capture_stderr: Option<bool>,
+
+    /// Where the key of each command in `commands` is defined.
+    #[serde(skip)]
+    command_keys: Vec<KeyEntry>,
+    /// Files read through `include`.
+    #[serde(skip)]
+    pub included: Vec<PathBuf>,
 }
 
 #[derive(Debug, Clone, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct Command {
-    key: String,
+    key: Spanned<String>,
     desc: String,
-    command: String,
+    /// Script run with `shell`. Placeholders like `{rev}` and
+    /// `{input:Message}` are picked before it runs.
+    command: Option<String>,
+    /// Program and arguments run without a shell.
+    argv: Option<Vec<String>>,
 
+    /// Same as `mode = "term"`.
     #[serde(default)]
     term: bool,
+    #[serde(default)]
+    mode: Mode,
+
+    /// Working directory, relative to the directory the command runs in.
+    cwd: Option<PathBuf>,
+    /// Directory of the project declaring the command, which it runs in.
+    /// User commands run in the current directory.
+    #[serde(skip)]
+    dir: Option<PathBuf>,
+    #[serde(default)]
+    env: BTreeMap<String, String>,
+    /// Shell running `command`, defaults to bash.
+    shell: Option<String>,
+
+    #[serde(default)]
+    args: Vec<CommandArg>,
+
+    /// Child commands, shown on their own page.
+    #[serde(default)]
+    commands: Vec<Command>,
+
+    /// Commands run one after another in the foreground, stopping at the
+    /// first failure.
+    #[serde(default)]
+    steps: Vec<Step>,
+
+    /// Only show the command when this holds.
+    when: Option<Predicate>,
+}
+
+/// One command of `steps`, sharing `cwd`, `env` and `shell` with the command
+/// it belongs to.
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Step {
+    command: Option<String>,
+    argv: Option<Vec<String>>,
+    /// Keep going with the next step when this one fails.
+    #[serde(default)]
+    continue_on_error: bool,
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CommandArg {
+    key: Spanned<String>,
+    desc: String,
+    /// Flag passed to the command, e.g. `--release` or `-p`.
+    flag: String,
+    /// Without a value source the argument is a boolean switch.
+    value: Option<ValueSource>,
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ValueSource {
+    /// Free text read at the prompt.
+    Input,
+    /// Fixed list of values picked with fzf.
+    Choices(Vec<String>),
+    /// Shell command whose output lines are picked with fzf.
+    Picker(String),
+}
+
+/// Resolves `include` relative to the `including` file, expanding `~/`.
+fn resolve_include(including: &Path, include: &str) -> PathBuf {
+    if let (Some(rest), Some(home)) = (include.strip_prefix("~/"), dirs::home_dir()) {
+        return home.join(rest);
+    }
+    including.parent().unwrap_or(Path::new("")).join(include)
+}
+
+fn canonical(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_owned())
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Mode {
+    #[default]
+    Foreground,
+    /// In a new multiplexer window.
+    Term,
+    /// On the runtime, with progress in the status area.
+    Background,
+}
+
+/// A condition on the environment humsh runs in, e.g. `when = "jj"` or
+/// `when = { file_exists = "Cargo.toml" }`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Predicate {
+    Git,
+    Jj,
+    FileExists(PathBuf),
+    /// The variable is set and not empty.
+    Env(String),
+    /// The bash command exits successfully.
+    CommandSucceeds(String),
+    All(Vec<Predicate>),
+    Any(Vec<Predicate>),
+    Not(Box<Predicate>),
 }
 
 impl Config {
+    /// Parses and checks the config at `path`. The config is returned
+    /// whenever it parses, even if checking it found problems.
+    pub fn load(path: &Path) -> (Option<Config>, Vec<Diagnostic>) {
+        Config::load_included(path, &mut vec![canonical(path)])
+    }
+
+    /// `chain` holds the files being loaded, from the outermost one to
+    /// `path`, to detect cycles.
+    fn load_included(path: &Path, chain: &mut Vec<PathBuf>) -> (Option<Config>, Vec<Diagnostic>) {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) => return (None, vec![Diagnostic::read_error(path, e)]),
+        };
+        let mut source = Source::new(path, &text);
+        let Some(mut config) = source.parse::<Config>() else {
+            return (None, source.diagnostics);
+        };
+        config.check(&mut source);
+        for include in std::mem::take(&mut config.include) {
+            let included_path = resolve_include(path, include.get_ref());
+            let included_canonical = canonical(&included_path);
+            if let Some(start) = chain.iter().position(|x| *x == included_canonical) {
+                let cycle = chain[start..]
+                    .iter()
+                    .chain([&included_canonical])
+                    .map(|x| x.display().to_string())
+                    .collect::<Vec<_>>()
+                    .join(" -> ");
+                source.error(include.span(), format!("include cycle: {cycle}"));
+                continue;
+            }
+            chain.push(included_canonical);
+            let (included, diagnostics) = Config::load_included(&included_path, chain);
+            chain.pop();
+            let location = source.location(include.span());
+            source
+                .diagnostics
+                .extend(diagnostics.into_iter().map(|mut diagnostic| {
+                    diagnostic.included_from.push(location.clone());
+                    diagnostic
+                }));
+            config.included.push(included_path);
+            if let Some(included) = included {
+                config.add_included(included);
+            }
+        }
+        (Some(config), source.diagnostics)
+    }
+
+    /// Adds the commands of an included config. Settings of this config take
+    /// precedence.
+    fn add_included(&mut self, included: Config) {
+        self.commands.extend(included.commands);
+        self.command_keys.extend(included.command_keys);
+        self.git = self.git.or(included.git);
+        self.cp = self.cp.or(included.cp);
+        self.theme = self.theme.take().or(included.theme);
+        self.editor = self.editor.take().or(included.editor);
+        self.capture_stderr = self.capture_stderr.or(included.capture_stderr);
+        self.keys.add_included(included.keys);
+        self.included.extend(included.included);
+    }
+
+    /// Like [`Config::load`], but fails on the first error.
     pub fn read(path: impl AsRef<Path>) -> anyhow::Result<Config> {
-        Ok(toml::from_str(&fs::read_to_string(path)?)?)
+        let (config, diagnostics) = Config::load(path.as_ref());
+        if let Some(error) = diagnostics.iter().find(|x| x.level == Level::Error) {
+            bail!("{error}");
+        }
+        config.context("config did not parse")
+    }
+
+    /// Merges project configs, nearest first. A command is dropped when a
+    /// nearer config binds its key.
+    pub fn merge(configs: impl IntoIterator<Item = Config>) -> Option<Config> {
+        let mut configs = configs.into_iter();
+        let mut merged = configs.next()?;
+        for config in configs {
+            merged.git = merged.git.or(config.git);
+            merged.cp = merged.cp.or(config.cp);
+            merged.included.extend(config.included);
+            for (command, key) in config.commands.into_iter().zip(config.command_keys) {
+                if merged
+                    .commands
+                    .iter()
+                    .all(|x| x.key.get_ref() != command.key.get_ref())
+                {
+                    merged.commands.push(command);
+                    merged.command_keys.push(key);
+                }
+            }
+        }
+        Some(merged)
+    }
+
+    /// Makes the commands run in `dir` instead of the current directory.
+    pub fn set_dir(&mut self, dir: &Path) {
+        for command in &mut self.commands {
+            command.set_dir(dir);
+        }
     }
 
-    pub fn command_buttons(&self) -> Vec<data::Button> {
+    fn check(&mut self, source: &mut Source) {
+        for command in &self.commands {
+            command.check(source);
+        }
+        // commands with a `when` may share a key with one that is not shown
+        // at the same time
+        source.check_keys(
+            self.commands
+                .iter()
+                .filter(|x| x.when.is_none())
+                .map(|x| &x.key),
+        );
+        self.keys.check(source);
+        self.command_keys = self
+            .commands
+            .iter()
+            .map(|x| source.key_entry(&x.key))
+            .collect();
+    }
+
+    pub fn command_buttons(&self, shell: &ShellContext) -> Vec<data::Button> {
         self.commands
-            .clone()
-            .into_iter()
-            .map(|x| {
-                data::button(&x.key, x.desc, move |mut ctx| {
-                    let mut command = std::process::Command::new("bash");
-                    command.arg("-c").arg(&x.command);
-                    if x.term {
-                        ctx.run_command_new_term(&mut command)?;
-                    } else {
-                        ctx.leave_ui()?;
-                        ctx.show_cmd()?;
-                        ctx.hint_running_command(&x.command)?;
-                        ctx.run_command_in_foreground(&mut command)?;
-                    }
-                    Ok(())
-                })
+            .iter()
+            .filter(|x| x.visible(shell))
+            .map(|x| x.button(shell))
+            .collect()
+    }
+
+    /// Every command, shown but not runnable, without evaluating `when`.
+    pub fn disabled_buttons(&self) -> Vec<data::Button> {
+        self.commands
+            .iter()
+            .map(|x| data::Button {
+                disabled: true,
+                ..data::button(x.key.get_ref(), &x.desc, |_| Ok(()))
             })
             .collect()
     }
+
+    /// Keys of the visible commands, which are shown on the home page.
+    pub fn home_keys<'a>(&'a self, shell: &'a ShellContext) -> impl Iterator<Item = &'a KeyEntry> {
+        self.commands
+            .iter()
+            .zip(&self.command_keys)
+            .filter(|(command, _)| command.visible(shell))
+            .map(|(_, key)| key)
+    }
+}
+
+impl Command {
+    fn check(&self, source: &mut Source) {
+        let kinds = [
+            self.command.is_some(),
+            self.argv.is_some(),
+            !self.commands.is_empty(),
+            !self.steps.is_empty(),
+        ];
+        if kinds.iter().filter(|x| **x).count() != 1 {
+            source.error(
+                self.key.span(),
+                "needs exactly one of `command`, `argv`, `commands` or `steps`",
+            );
+        }
+        if self.argv.as_ref().is_some_and(|x| x.is_empty()) {
+            source.error(self.key.span(), "`argv` must not be empty");
+        }
+        if self.term && self.mode != Mode::Foreground {
+            source.error(self.key.span(), "`term` can not be used with `mode`");
+        }
+        if self.shell.is_some() && self.command.is_none() && self.steps.is_empty() {
+            source.error(
+                self.key.span(),
+                "`shell` is only allowed with `command` or `steps`",
+            );
+        }
+        if !self.steps.is_empty() {
+            if self.term || self.mode != Mode::Foreground {
+                source.error(self.key.span(), "`steps` only run in the foreground");
+            }
+            if !self.args.is_empty() {
+                source.error(self.key.span(), "`args` can not be used with `steps`");
+            }
+        }
+        for (i, step) in self.steps.iter().enumerate() {
+            if step.command.is_some() == step.argv.is_some() {
+                source.error(
+                    self.key.span(),
+                    format!("step {} needs exactly one of `command` or `argv`", i + 1),
+                );
+            }
+            if step.argv.as_ref().is_some_and(|x| x.is_empty()) {
+                source.error(
+                    self.key.span(),
+                    format!("`argv` of step {} must not be empty", i + 1),
+                );
+            }
+        }
+        if !self.args.is_empty() {
+            // the run button shares the arguments page
+            source.check_keys(self.args.iter().map(|x| &x.key).chain([&self.key]));
+        }
+        if !self.commands.is_empty() {
+            source.check_keys(self.commands.iter().map(|x| &x.key));
+        }
+        for command in &self.commands {
+            command.check(source);
+        }
+    }
+
+    fn set_dir(&mut self, dir: &Path) {
+        self.dir = Some(dir.to_owned());
+        for command in &mut self.commands {
+            command.set_dir(dir);
+        }
+    }
+
+    fn visible(&self, shell: &ShellContext) -> bool {
+        self.when.as_ref().map_or(true, |x| shell.check(x))
+    }
+
+    fn button(&self, shell: &ShellContext) -> data::Button {
+        if !self.commands.is_empty() {
+            let page = data::page([data::group(
+                &self.desc,
+                self.commands
+                    .iter()
+                    .filter(|x| x.visible(shell))
+                    .map(|x| x.button(shell))
+                    .collect::<Vec<_>>(),
+            )]);
+            return data::page_button(self.key.get_ref(), &self.desc, [], page);
+        }
+
+        let cmd = self.clone();
+        if self.args.is_empty() {
+            return data::button(self.key.get_ref(), &self.desc, move |mut ctx| {
+                cmd.run(&mut ctx, Vec::new())
+            });
+        }
+
+        let run = data::button(self.key.get_ref(), &self.desc, move |mut ctx| {
+            let mut values = Vec::new();
+            for arg in &ctx.command_line().args {
+                if arg.order >= ArgOrder::FLAG {
+                    arg.value.add_to(&mut values);
+                }
+            }
+            let result = cmd.run(&mut ctx, values);
+            ctx.pop_page();
+            result
+        });
+        let page = data::args_page(
+            self.args.iter().map(CommandArg::button).collect::<Vec<_>>(),
+            [run],
+        );
+        data::page_button(self.key.get_ref(), &self.desc, self.command_line(), page)
+    }
+
+    /// The command as the program and subcommands of a command line, which
+    /// the flags of the args page follow. Scripts, and commands that need a
+    /// directory or environment, run through the shell with the flags as
+    /// `"$@"`.
+    fn command_line(&self) -> Vec<Arg> {
+        let argv = self
+            .argv
+            .as_ref()
+            .filter(|_| self.dir.is_none() && self.cwd.is_none() && self.env.is_empty());
+        let words = match argv {
+            Some(argv) => argv.clone(),
+            None => {
+                let mut script = String::new();
+                let dir = match (&self.dir, &self.cwd) {
+                    (Some(dir), Some(cwd)) => Some(dir.join(cwd)),
+                    (dir, cwd) => dir.clone().or(cwd.clone()),
+                };
+                if let Some(dir) = dir {
+                    script.push_str(&format!("cd {} && ", shell_quote(&dir.to_string_lossy())));
+                }
+                for (key, value) in &self.env {
+                    script.push_str(&format!("export {key}={} && ", shell_quote(value)));
+                }
+                script.push_str(&self.to_shell(&[]));
+                script.push_str(" \"$@\"");
+                let shell = self.shell.clone().unwrap_or_else(|| "bash".to_owned());
+                vec![shell.clone(), "-c".to_owned(), script, shell]
+            }
+        };
+        let mut words = words.into_iter();
+        words
+            .next()
+            .map(Arg::program)
+            .into_iter()
+            .chain(
+                words
+                    .enumerate()
+                    .map(|(i, x)| Arg::new(ArgOrder::subcommand(i as u64), ArgValue::Single(x))),
+            )
+            .collect()
+    }
+
+    /// The command with `extra_args` appended, as typed in a shell.
+    fn to_shell(&self, extra_args: &[String]) -> String {
+        let mut script = match (&self.command, &self.argv) {
+            (Some(command), _) => command.clone(),
+            (None, Some(argv)) => argv
+                .iter()
+                .map(|x| shell_quote(x))
+                .collect::<Vec<_>>()
+                .join(" "),
+            (None, None) => String::new(),
+        };
+        for arg in extra_args {
+            script.push(' ');
+            script.push_str(&shell_quote(arg));
+        }
+        script
+    }
+
+    /// Shell commands are emitted as written rather than as `bash -c`.
+    fn emit_as(&self, ctx: &mut Context, extra_args: &[String], continue_on_error: bool) {
+        if self.command.is_some() || continue_on_error {
+            ctx.emit_as(self.to_shell(extra_args), continue_on_error);
+        }
+    }
+
+    fn to_std(&self, extra_args: &[String]) -> anyhow::Result<std::process::Command> {
+        let mut command = match &self.argv {
+            Some(argv) => {
+                let (program, args) = argv.split_first().context("`argv` is empty")?;
+                let mut command = std::process::Command::new(program);
+                command.args(args).args(extra_args);
+                command
+            }
+            None => {
+                let mut command =
+                    std::process::Command::new(self.shell.as_deref().unwrap_or("bash"));
+                command.arg("-c").arg(self.to_shell(extra_args));
+                command
+            }
+        };
+        match (&self.dir, &self.cwd) {
+            (Some(dir), Some(cwd)) => command.current_dir(dir.join(cwd)),
+            (Some(dir), None) => command.current_dir(dir),
+            (None, Some(cwd)) => command.current_dir(std::env::current_dir()?.join(cwd)),
+            (None, None) => &mut command,
+        };
+        command.envs(&self.env);
+        Ok(command)
+    }
+
+    fn run(&self, ctx: &mut Context, extra_args: Vec<String>) -> anyhow::Result<()> {
+        if !self.steps.is_empty() {
+            return self.run_steps(ctx);
+        }
+        match self.expand(ctx)? {
+            Some(expanded) => expanded.run_expanded(ctx, extra_args),
+            None => Ok(()),
+        }
+    }
+
+    /// The command with its placeholders filled in, `None` when a picker was
+    /// cancelled.
+    fn expand(&self, ctx: &mut Context) -> anyhow::Result<Option<Command>> {
+        let Some(script) = &self.command else {
+            return Ok(Some(self.clone()));
+        };
+        Ok(placeholder::expand(script, ctx)?.map(|script| Command {
+            command: Some(script),
+            ..self.clone()
+        }))
+    }
+
+    /// Picks the placeholders of every step before running the first one.
+    fn run_steps(&self, ctx: &mut Context) -> anyhow::Result<()> {
+        let mut steps = Vec::new();
+        for step in &self.steps {
+            let command = Command {
+                command: step.command.clone(),
+                argv: step.argv.clone(),
+                steps: Vec::new(),
+                ..self.clone()
+            };
+            let Some(command) = command.expand(ctx)? else {
+                return Ok(());
+            };
+            steps.push((command, step.continue_on_error));
+        }
+        ctx.leave_ui()?;
+        ctx.show_cmd()?;
+        let count = steps.len();
+        for (i, (command, continue_on_error)) in steps.iter().enumerate() {
+            let script = command.to_shell(&[]);
+            ctx.hint_running_command(&script)?;
+            let mut std_command = command.to_std(&[])?;
+            command.emit_as(ctx, &[], *continue_on_error);
+            let Err(e) = ctx.run_command_in_foreground(&mut std_command) else {
+                continue;
+            };
+            let e = e.context(format!("step {}/{count} `{script}` failed", i + 1));
+            if !continue_on_error {
+                return Err(e);
+            }
+            ctx.print_error(&format!("{e:#}"))?;
+        }
+        Ok(())
+    }
+
+    /// Runs the command after its placeholders were filled in.
+    fn run_expanded(&self, ctx: &mut Context, extra_args: Vec<String>) -> anyhow::Result<()> {
+        let mut command = self.to_std(&extra_args)?;
+        let mode = if self.term { Mode::Term } else { self.mode };
+        self.emit_as(ctx, &extra_args, false);
+        match mode {
+            Mode::Term => ctx.run_command_new_term(&mut command)?,
+            Mode::Background => ctx.run_command_in_background(&self.desc, command)?,
+            Mode::Foreground => {
+                ctx.leave_ui()?;
+                ctx.show_cmd()?;
+                ctx.hint_running_command(&self.to_shell(&extra_args))?;
+                ctx.run_command_in_foreground(&mut command)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl CommandArg {
+    fn button(&self) -> data::Button {
+        match &self.value {
+            None => data::flag_button(self.key.get_ref(), &self.desc, self.flag.clone()),
+            Some(source) => {
+                let source = source.clone();
+                let prompt = self.desc.clone();
+                data::prompt_button(self.key.get_ref(), &self.desc, &self.flag, move |ctx| {
+                    source.read(ctx, &prompt)
+                })
+            }
+        }
+    }
+}
+
+impl ValueSource {
+    pub fn read(&self, ctx: &mut Context, prompt: &str) -> anyhow::Result<Vec<String>> {
+        match self {
+            ValueSource::Input => {
+                let input = ctx.read_input(prompt)?;
+                Ok(if input.is_empty() {
+                    vec![]
+                } else {
+                    vec![input]
+                })
+            }
+            ValueSource::Choices(choices) => {
+                ctx.leave_ui()?;
+                data::select_from_list(choices, prompt)
+            }
+            ValueSource::Picker(command) => {
+                ctx.leave_ui()?;
+                data::select_from_command(command, prompt)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(files: &[(&str, &str)]) -> (tempfile::TempDir, Vec<Diagnostic>) {
+        let dir = tempfile::tempdir().unwrap();
+        for (name, text) in files {
+            fs::write(dir.path().join(name), text).unwrap();
+        }
+        let (config, diagnostics) = Config::load(&dir.path().join(files[0].0));
+        assert!(config.is_some());
+        (dir, diagnostics)
+    }
+
+    fn cycle(dir: &Path, names: &[&str]) -> String {
+        let dir = fs::canonicalize(dir).unwrap();
+        let names = names
+            .iter()
+            .map(|x| dir.join(x).display().to_string())
+            .collect::<Vec<_>>();
+        format!("include cycle: {}", names.join(" -> "))
+    }
+
+    #[test]
+    fn reports_include_cycles() {
+        let (dir, diagnostics) = load(&[
+            ("a.toml", "include = [\"b.toml\"]\n"),
+            ("b.toml", "include = [\"c.toml\"]\n"),
+            ("c.toml", "\ninclude = [\"b.toml\"]\n"),
+        ]);
+        let [diagnostic] = &diagnostics[..] else {
+            panic!("{diagnostics:?}");
+        };
+        assert_eq!(
+            diagnostic.message,
+            cycle(dir.path(), &["b.toml", "c.toml", "b.toml"])
+        );
+        let location = diagnostic.location.as_ref().unwrap();
+        assert_eq!(
+            (location.path.file_name().unwrap(), location.line),
+            ("c.toml".as_ref(), 2)
+        );
+        let included_from = diagnostic
+            .included_from
+            .iter()
+            .map(|x| x.path.file_name().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(included_from, ["b.toml", "a.toml"]);
+    }
+
+    #[test]
+    fn reports_a_config_including_itself() {
+        let (dir, diagnostics) = load(&[("a.toml", "include = [\"./a.toml\"]\n")]);
+        let messages = diagnostics.iter().map(|x| &x.message).collect::<Vec<_>>();
+        assert_eq!(messages, [&cycle(dir.path(), &["a.toml", "a.toml"])]);
+    }
+
+    #[test]
+    fn allows_including_a_file_twice() {
+        let (_dir, diagnostics) = load(&[
+            ("a.toml", "include = [\"b.toml\", \"c.toml\"]\n"),
+            ("b.toml", "include = [\"d.toml\"]\n"),
+            ("c.toml", "include = [\"d.toml\"]\n"),
+            ("d.toml", ""),
+        ]);
+        assert!(diagnostics.is_empty(), "{diagnostics:?}");
+    }
 }
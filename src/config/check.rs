@@ -0,0 +1,244 @@
+use std::{
+    fmt,
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use toml::Spanned;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    Error,
+    Warning,
+}
+
+#[derive(Debug, Clone)]
+pub struct Location {
+    pub path: PathBuf,
+    pub line: usize,
+    pub column: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub level: Level,
+    pub location: Option<Location>,
+    pub message: String,
+    /// Where the file with the problem was included, innermost first.
+    pub included_from: Vec<Location>,
+}
+
+/// A keybinding on a page and where it was defined, `None` for builtin
+/// buttons.
+#[derive(Debug, Clone)]
+pub struct KeyEntry {
+    pub key: String,
+    pub location: Option<Location>,
+}
+
+/// A config file being checked, collecting diagnostics as it goes.
+pub struct Source<'a> {
+    path: &'a Path,
+    text: &'a str,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl Diagnostic {
+    pub fn read_error(path: &Path, error: std::io::Error) -> Self {
+        Self {
+            level: Level::Error,
+            location: None,
+            message: format!("reading {}: {error}", path.display()),
+            included_from: Vec::new(),
+        }
+    }
+}
+
+impl<'a> Source<'a> {
+    pub fn new(path: &'a Path, text: &'a str) -> Self {
+        Self {
+            path,
+            text,
+            diagnostics: Vec::new(),
+        }
+    }
+
+    pub fn text(&self) -> &'a str {
+        self.text
+    }
+
+    pub fn location(&self, span: Range<usize>) -> Location {
+        let before = &self.text[..span.start.min(self.text.len())];
+        let line_start = before.rfind('\n').map_or(0, |x| x + 1);
+        Location {
+            path: self.path.to_owned(),
+            line: before.matches('\n').count() + 1,
+            column: before[line_start..].chars().count() + 1,
+        }
+    }
+
+    pub fn parse<T: serde::de::DeserializeOwned>(&mut self) -> Option<T> {
+        match toml::from_str(self.text) {
+            Ok(value) => Some(value),
+            Err(e) => {
+                self.diagnostics.push(Diagnostic {
+                    level: Level::Error,
+                    location: e.span().map(|span| self.location(span)),
+                    message: e.message().to_owned(),
+                    included_from: Vec::new(),
+                });
+                None
+            }
+        }
+    }
+
+    pub fn error(&mut self, span: Range<usize>, message: impl Into<String>) {
+        self.diagnostics.push(Diagnostic {
+            level: Level::Error,
+            location: Some(self.location(span)),
+            message: message.into(),
+            included_from: Vec::new(),
+        });
+    }
+
+    pub fn key_entry(&self, key: &Spanned<String>) -> KeyEntry {
+        KeyEntry {
+            key: key.get_ref().clone(),
+            location: Some(self.location(key.span())),
+        }
+    }
+
+    /// Checks the keys of buttons shown together on one page.
+    pub fn check_keys<'k>(&mut self, keys: impl IntoIterator<Item = &'k Spanned<String>>) {
+        let entries = keys
+            .into_iter()
+            .map(|key| self.key_entry(key))
+            .collect::<Vec<_>>();
+        check_keys(&entries, &mut self.diagnostics);
+    }
+}
+
+/// Reports duplicate keys, and keys that can never be typed because a prefix
+/// of them is bound on the same page.
+pub fn check_keys(keys: &[KeyEntry], diagnostics: &mut Vec<Diagnostic>) {
+    for (i, entry) in keys.iter().enumerate() {
+        if entry.key.is_empty() {
+            diagnostics.push(Diagnostic {
+                level: Level::Error,
+                location: entry.location.clone(),
+                message: "key must not be empty".into(),
+                included_from: Vec::new(),
+            });
+            continue;
+        }
+        for other in &keys[..i] {
+            if other.key.is_empty() {
+                continue;
+            }
+            let (level, shadowed, bound) = if entry.key == other.key {
+                (Level::Error, entry, other)
+            } else if entry.key.starts_with(&other.key) {
+                (Level::Warning, entry, other)
+            } else if other.key.starts_with(&entry.key) {
+                (Level::Warning, other, entry)
+            } else {
+                continue;
+            };
+            let message = if level == Level::Error {
+                format!("duplicate key `{}`, already bound {bound}", shadowed.key)
+            } else {
+                format!(
+                    "`{}` is unreachable, `{}` is bound {bound}",
+                    shadowed.key, bound.key
+                )
+            };
+            diagnostics.push(Diagnostic {
+                level,
+                location: shadowed.location.clone(),
+                message,
+                included_from: Vec::new(),
+            });
+        }
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.path.display(), self.line, self.column)
+    }
+}
+
+impl fmt::Display for KeyEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.location {
+            Some(location) => write!(f, "at {location}"),
+            None => write!(f, "by a builtin button"),
+        }
+    }
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Level::Error => "error",
+            Level::Warning => "warning",
+        })
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(location) = &self.location {
+            write!(f, "{location}: ")?;
+        }
+        write!(f, "{}: {}", self.level, self.message)?;
+        for location in &self.included_from {
+            write!(f, "\n  included from {location}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(keys: &[&str]) -> Vec<(Level, String)> {
+        let keys = keys
+            .iter()
+            .map(|x| KeyEntry {
+                key: x.to_string(),
+                location: None,
+            })
+            .collect::<Vec<_>>();
+        let mut diagnostics = Vec::new();
+        check_keys(&keys, &mut diagnostics);
+        diagnostics
+            .into_iter()
+            .map(|x| (x.level, x.message))
+            .collect()
+    }
+
+    #[test]
+    fn distinct_keys_are_fine() {
+        assert!(check(&["a", "b", "ca", "cb"]).is_empty());
+    }
+
+    #[test]
+    fn reports_duplicates_and_prefixes() {
+        assert_eq!(
+            check(&["a", "a", "gb", "g", ""]),
+            [
+                (
+                    Level::Error,
+                    "duplicate key `a`, already bound by a builtin button".to_owned()
+                ),
+                (
+                    Level::Warning,
+                    "`gb` is unreachable, `g` is bound by a builtin button".to_owned()
+                ),
+                (Level::Error, "key must not be empty".to_owned()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,400 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt,
+    ops::Range,
+    sync::Arc,
+};
+
+use serde::de;
+
+use crate::data::{Button, Keybind, Page, SubPage};
+
+use super::check::Location;
+use super::{check_keys, Diagnostic, KeyEntry, Level, Source};
+
+/// The `[keys]` section, rebinding builtin buttons by the path of their
+/// descriptions, e.g. `jj.push.dry_run = "D"`, or hiding them with
+/// `jj.abandon = false`.
+#[derive(Debug, Clone, Default, serde::Deserialize)]
+#[serde(from = "BTreeMap<String, KeyNode>")]
+pub struct KeyMap {
+    remaps: Vec<Remap>,
+}
+
+#[derive(Debug, Clone)]
+struct Remap {
+    path: String,
+    /// The keys in the toml that lead to it, `"jj.push"` is one key and
+    /// `jj.push` two.
+    keys: Vec<String>,
+    /// `None` hides the button.
+    key: Option<String>,
+    span: Option<Range<usize>>,
+    location: Option<Location>,
+}
+
+/// A value in `[keys]`.
+enum KeyNode {
+    Leaf(Leaf),
+    Table(BTreeMap<String, KeyNode>),
+}
+
+/// A key, or `false` to hide the button.
+struct Leaf(Option<String>);
+
+impl<'de> serde::Deserialize<'de> for KeyNode {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(KeyNodeVisitor)
+    }
+}
+
+struct KeyNodeVisitor;
+
+impl<'de> de::Visitor<'de> for KeyNodeVisitor {
+    type Value = KeyNode;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a key, `false` or a table")
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<KeyNode, E> {
+        Ok(KeyNode::Leaf(Leaf(Some(value.to_owned()))))
+    }
+
+    fn visit_bool<E: de::Error>(self, value: bool) -> Result<KeyNode, E> {
+        if value {
+            return Err(E::invalid_value(de::Unexpected::Bool(true), &self));
+        }
+        Ok(KeyNode::Leaf(Leaf(None)))
+    }
+
+    fn visit_map<A: de::MapAccess<'de>>(self, mut map: A) -> Result<KeyNode, A::Error> {
+        let mut table = BTreeMap::new();
+        while let Some(name) = map.next_key::<String>()? {
+            table.insert(name, map.next_value()?);
+        }
+        Ok(KeyNode::Table(table))
+    }
+}
+
+impl From<BTreeMap<String, KeyNode>> for KeyMap {
+    fn from(table: BTreeMap<String, KeyNode>) -> Self {
+        let mut remaps = Vec::new();
+        flatten(&[], table, &mut remaps);
+        Self { remaps }
+    }
+}
+
+/// Quoted keys like `"jj.push" = "P"` can be mixed with tables like
+/// `jj.push.dry_run = "D"`, which could not both set `push` otherwise.
+fn flatten(keys: &[String], table: BTreeMap<String, KeyNode>, remaps: &mut Vec<Remap>) {
+    for (name, node) in table {
+        let mut keys = keys.to_vec();
+        keys.push(name);
+        match node {
+            KeyNode::Table(table) => flatten(&keys, table, remaps),
+            KeyNode::Leaf(Leaf(key)) => remaps.push(Remap {
+                path: keys.join("."),
+                keys,
+                key,
+                span: None,
+                location: None,
+            }),
+        }
+    }
+}
+
+/// The name of a button in a path, e.g. `dry_run` for "Dry run".
+pub fn key_name(description: &str) -> String {
+    description
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|x| !x.is_empty())
+        .map(|x| x.to_lowercase())
+        .collect::<Vec<_>>()
+        .join("_")
+}
+
+fn buttons(page: &Page) -> impl Iterator<Item = &Button> {
+    page.groups.iter().flat_map(|x| &x.buttons)
+}
+
+fn sub_page(button: &Button) -> Option<&SubPage> {
+    button.handler.as_any().downcast_ref::<SubPage>()
+}
+
+impl KeyMap {
+    pub fn check(&mut self, source: &mut Source) {
+        // serde has no spans for values in dotted tables like `jj.push`, so
+        // they are looked up in the document instead
+        let document = toml_edit::ImDocument::parse(source.text()).ok();
+        for remap in &mut self.remaps {
+            remap.span = document.as_ref().and_then(|document| {
+                let keys = document.as_item().get("keys")?;
+                let item = remap
+                    .keys
+                    .iter()
+                    .try_fold(keys, |item, key| item.get(key.as_str()))?;
+                item.span()
+            });
+            let Some(span) = remap.span.clone() else {
+                continue;
+            };
+            remap.location = Some(source.location(span.clone()));
+            if remap.key.as_deref() == Some("") {
+                source.error(span, "key must not be empty");
+            }
+        }
+    }
+
+    /// Adds the remaps of an included config, which this one overrides.
+    pub fn add_included(&mut self, included: KeyMap) {
+        self.remaps.splice(0..0, included.remaps);
+    }
+
+    fn find(&self, path: &str) -> Option<&Remap> {
+        self.remaps.iter().rev().find(|x| x.path == path)
+    }
+
+    /// Rebinds and hides `buttons`, which are at `path`, and the buttons on
+    /// the pages they open.
+    #[allow(clippy::arc_with_non_send_sync)]
+    pub fn apply(&self, path: &str, buttons: Vec<Button>) -> Vec<Button> {
+        if self.remaps.is_empty() {
+            return buttons;
+        }
+        buttons
+            .into_iter()
+            .filter_map(|mut button| {
+                if button.hidden {
+                    return Some(button);
+                }
+                let path = format!("{path}.{}", key_name(&button.description));
+                if let Some(remap) = self.find(&path) {
+                    button.key = Keybind(remap.key.clone()?);
+                }
+                if let Some(sub_page) = sub_page(&button) {
+                    button.handler = Arc::new(SubPage {
+                        page: self.apply_page(&path, sub_page.page.clone()),
+                        args: sub_page.args.clone(),
+                    });
+                }
+                Some(button)
+            })
+            .collect()
+    }
+
+    pub fn apply_page(&self, path: &str, mut page: Page) -> Page {
+        if self.remaps.is_empty() {
+            return page;
+        }
+        for group in &mut page.groups {
+            group.buttons = self.apply(path, std::mem::take(&mut group.buttons));
+        }
+        page.groups.retain(|x| !x.buttons.is_empty());
+        page
+    }
+
+    /// Where `button`, at `path`, gets its key from.
+    pub fn key_entry(&self, path: &str, button: &Button) -> KeyEntry {
+        let location = if button.hidden {
+            None
+        } else {
+            self.find(&format!("{path}.{}", key_name(&button.description)))
+                .and_then(|x| x.location.clone())
+        };
+        KeyEntry {
+            key: button.key.0.clone(),
+            location,
+        }
+    }
+
+    /// Reports remaps that do not name a button in any of the builtin
+    /// `roots`, before remapping.
+    pub fn check_paths(&self, roots: &[(&str, Page)], diagnostics: &mut Vec<Diagnostic>) {
+        let mut paths = HashSet::new();
+        for (root, page) in roots {
+            collect_paths(root, page, &mut paths);
+        }
+        for remap in &self.remaps {
+            if !paths.contains(&remap.path) {
+                diagnostics.push(Diagnostic {
+                    level: Level::Error,
+                    location: remap.location.clone(),
+                    message: format!("`{}` is not a builtin button", remap.path),
+                    included_from: Vec::new(),
+                });
+            }
+        }
+    }
+
+    /// Reports keys that collide on `page`, at `path`, and the pages below it
+    /// after remapping.
+    pub fn check_page(&self, path: &str, page: &Page, diagnostics: &mut Vec<Diagnostic>) {
+        let mut entries = buttons(page)
+            .map(|x| self.key_entry(path, x))
+            .collect::<Vec<_>>();
+        // report the remapped key rather than the builtin one it collides with
+        entries.sort_by_key(|x| x.location.is_some());
+        check_keys(&entries, diagnostics);
+        for button in buttons(page) {
+            if let Some(sub_page) = sub_page(button) {
+                let path = format!("{path}.{}", key_name(&button.description));
+                self.check_page(&path, &sub_page.page, diagnostics);
+            }
+        }
+    }
+}
+
+fn collect_paths(path: &str, page: &Page, paths: &mut HashSet<String>) {
+    for button in buttons(page).filter(|x| !x.hidden) {
+        let path = format!("{path}.{}", key_name(&button.description));
+        if let Some(sub_page) = sub_page(button) {
+            collect_paths(&path, &sub_page.page, paths);
+        }
+        paths.insert(path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::data::{button, group, page, page_button};
+
+    #[derive(serde::Deserialize)]
+    struct File {
+        keys: KeyMap,
+    }
+
+    fn key_map(text: &str) -> (KeyMap, Vec<Diagnostic>) {
+        let mut source = Source::new(Path::new("config.toml"), text);
+        let mut keys = source.parse::<File>().unwrap().keys;
+        keys.check(&mut source);
+        (keys, source.diagnostics)
+    }
+
+    fn jj_page() -> Page {
+        let push = page([group(
+            "Push",
+            [
+                button("d", "Dry run", |_| Ok(())),
+                button("p", "Push", |_| Ok(())),
+            ],
+        )]);
+        page([
+            group(
+                "Commands",
+                [
+                    page_button("p", "Push", [], push),
+                    button("a", "Abandon", |_| Ok(())),
+                ],
+            ),
+            group("Other", [button("e", "Edit", |_| Ok(()))]),
+        ])
+    }
+
+    fn bindings(page: &Page) -> Vec<(String, String)> {
+        buttons(page)
+            .map(|x| (x.key.0.clone(), x.description.clone()))
+            .collect()
+    }
+
+    fn messages(diagnostics: Vec<Diagnostic>) -> Vec<(Level, Option<usize>, String)> {
+        diagnostics
+            .into_iter()
+            .map(|x| (x.level, x.location.map(|x| x.line), x.message))
+            .collect()
+    }
+
+    #[test]
+    fn remaps_and_hides_buttons() {
+        let (keys, diagnostics) = key_map(
+            "[keys]\njj.push.dry_run = \"D\"\njj.abandon = false\n\"jj.push\" = \"P\"\njj.edit = false\n",
+        );
+        assert!(diagnostics.is_empty());
+        let page = keys.apply_page("jj", jj_page());
+        assert_eq!(
+            bindings(&page),
+            [("P".to_owned(), "Push".to_owned())],
+            "the Other group is left empty and dropped"
+        );
+        let push = sub_page(&page.groups[0].buttons[0]).unwrap();
+        assert_eq!(
+            bindings(&push.page),
+            [
+                ("D".to_owned(), "Dry run".to_owned()),
+                ("p".to_owned(), "Push".to_owned())
+            ]
+        );
+    }
+
+    #[test]
+    fn later_remaps_override_included_ones() {
+        let (mut keys, _) = key_map("[keys]\njj.abandon = \"A\"\n");
+        let (included, _) = key_map("[keys]\njj.abandon = \"B\"\njj.edit = \"E\"\n");
+        keys.add_included(included);
+        let page = keys.apply_page("jj", jj_page());
+        assert_eq!(
+            bindings(&page)[1..],
+            [
+                ("A".to_owned(), "Abandon".to_owned()),
+                ("E".to_owned(), "Edit".to_owned())
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_empty_keys() {
+        let (_, diagnostics) = key_map("[keys]\njj.abandon = \"\"\n");
+        assert_eq!(
+            messages(diagnostics),
+            [(Level::Error, Some(2), "key must not be empty".to_owned())]
+        );
+    }
+
+    #[test]
+    fn reports_collisions_after_remapping() {
+        let (keys, _) = key_map("[keys]\njj.abandon = \"e\"\njj.push.dry_run = \"pu\"\n");
+        let mut diagnostics = Vec::new();
+        keys.check_page("jj", &keys.apply_page("jj", jj_page()), &mut diagnostics);
+        assert_eq!(
+            messages(diagnostics),
+            [
+                (
+                    Level::Error,
+                    Some(2),
+                    "duplicate key `e`, already bound by a builtin button".to_owned()
+                ),
+                (
+                    Level::Warning,
+                    Some(3),
+                    "`pu` is unreachable, `p` is bound by a builtin button".to_owned()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_unknown_paths() {
+        let (keys, _) =
+            key_map("[keys]\njj.push.dry_run = \"D\"\njj.dry_run = \"D\"\ngit.push = \"P\"\n");
+        let mut diagnostics = Vec::new();
+        keys.check_paths(&[("jj", jj_page())], &mut diagnostics);
+        assert_eq!(
+            messages(diagnostics),
+            [
+                (
+                    Level::Error,
+                    Some(4),
+                    "`git.push` is not a builtin button".to_owned()
+                ),
+                (
+                    Level::Error,
+                    Some(3),
+                    "`jj.dry_run` is not a builtin button".to_owned()
+                ),
+            ]
+        );
+    }
+}
@@ -0,0 +1,193 @@
+use crate::data::{self, RevSelector};
+use crate::ui::Context;
+use crate::util::shell_quote;
+
+/// A `{name}` or `{name:argument}` in a `command`, filled in by a picker
+/// before the command runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Placeholder<'a> {
+    /// `{rev}`, picked from `jj log`.
+    Rev,
+    /// `{branch}`, picked from `jj branch list`.
+    Branch,
+    /// `{dir}`, picked like the Change Directory button.
+    Dir,
+    /// `{input}` or `{input:Message}`, read at the prompt.
+    Input(&'a str),
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Part<'a> {
+    Text(&'a str),
+    Placeholder(Placeholder<'a>, Quote),
+}
+
+/// The quotes a placeholder is written in, which decide how its value is
+/// escaped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Quote {
+    None,
+    Single,
+    Double,
+}
+
+impl Quote {
+    /// The quotes in effect after `text`.
+    fn after(mut self, text: &str) -> Quote {
+        let mut chars = text.chars();
+        while let Some(c) = chars.next() {
+            self = match (self, c) {
+                (Quote::None | Quote::Double, '\\') => {
+                    chars.next();
+                    self
+                }
+                (Quote::None, '\'') => Quote::Single,
+                (Quote::None, '"') => Quote::Double,
+                (Quote::Single, '\'') | (Quote::Double, '"') => Quote::None,
+                _ => self,
+            };
+        }
+        self
+    }
+
+    fn escape(self, value: &str) -> String {
+        match self {
+            Quote::None => shell_quote(value).into_owned(),
+            Quote::Single => value.replace('\'', r"'\''"),
+            Quote::Double => value
+                .chars()
+                .flat_map(|c| match c {
+                    '"' | '\\' | '$' | '`' => vec!['\\', c],
+                    c => vec![c],
+                })
+                .collect(),
+        }
+    }
+}
+
+impl<'a> Placeholder<'a> {
+    fn parse(inner: &'a str) -> Option<Self> {
+        match inner.split_once(':') {
+            None if inner == "rev" => Some(Placeholder::Rev),
+            None if inner == "branch" => Some(Placeholder::Branch),
+            None if inner == "dir" => Some(Placeholder::Dir),
+            None if inner == "input" => Some(Placeholder::Input("Input")),
+            Some(("input", message)) => Some(Placeholder::Input(message)),
+            _ => None,
+        }
+    }
+
+    /// `None` when nothing was picked.
+    fn pick(self, ctx: &mut Context, quote: Quote) -> anyhow::Result<Option<String>> {
+        let values = match self {
+            Placeholder::Rev => data::jj_select_rev("Revision: ", RevSelector::All)(ctx)?,
+            Placeholder::Branch => data::jj_select_branch("Branch: ")(ctx)?,
+            Placeholder::Dir => {
+                ctx.leave_ui()?;
+                vec![data::select_directory()?]
+            }
+            Placeholder::Input(message) => vec![ctx.read_input(message)?],
+        };
+        let values = values
+            .iter()
+            .filter(|x| !x.is_empty())
+            .map(|x| quote.escape(x))
+            .collect::<Vec<_>>();
+        Ok((!values.is_empty()).then(|| values.join(" ")))
+    }
+}
+
+/// Splits `command` into text and placeholders. Unknown names and `${...}`
+/// are kept as text, so shell syntax passes through untouched.
+fn parse(command: &str) -> Vec<Part<'_>> {
+    let mut parts = Vec::new();
+    let mut quote = Quote::None;
+    let mut text_start = 0;
+    let mut search = 0;
+    while let Some(open) = command[search..].find('{').map(|x| x + search) {
+        search = open + 1;
+        if command[..open].ends_with('$') {
+            continue;
+        }
+        let Some(close) = command[open..].find('}').map(|x| x + open) else {
+            break;
+        };
+        let Some(placeholder) = Placeholder::parse(&command[open + 1..close]) else {
+            continue;
+        };
+        quote = quote.after(&command[text_start..open]);
+        parts.push(Part::Text(&command[text_start..open]));
+        parts.push(Part::Placeholder(placeholder, quote));
+        text_start = close + 1;
+        search = close + 1;
+    }
+    parts.push(Part::Text(&command[text_start..]));
+    parts
+}
+
+/// Runs the picker of every placeholder in `command` and substitutes the
+/// values, quoted for the shell or escaped for the quotes the placeholder is
+/// in. `None` when a picker was cancelled.
+pub fn expand(command: &str, ctx: &mut Context) -> anyhow::Result<Option<String>> {
+    let mut expanded = String::new();
+    for part in parse(command) {
+        match part {
+            Part::Text(text) => expanded.push_str(text),
+            Part::Placeholder(placeholder, quote) => {
+                let Some(value) = placeholder.pick(ctx, quote)? else {
+                    return Ok(None);
+                };
+                expanded.push_str(&value);
+            }
+        }
+    }
+    Ok(Some(expanded))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_finds_placeholders() {
+        assert_eq!(
+            parse("jj new {rev} -m {input:Message}"),
+            [
+                Part::Text("jj new "),
+                Part::Placeholder(Placeholder::Rev, Quote::None),
+                Part::Text(" -m "),
+                Part::Placeholder(Placeholder::Input("Message"), Quote::None),
+                Part::Text(""),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_keeps_shell_syntax() {
+        assert_eq!(
+            parse("echo ${HOME} {unknown} {"),
+            [Part::Text("echo ${HOME} {unknown} {")]
+        );
+    }
+
+    #[test]
+    fn parse_tracks_quotes() {
+        assert_eq!(
+            parse(r#"git commit -m '{input}' && echo "{dir}""#),
+            [
+                Part::Text("git commit -m '"),
+                Part::Placeholder(Placeholder::Input("Input"), Quote::Single),
+                Part::Text(r#"' && echo ""#),
+                Part::Placeholder(Placeholder::Dir, Quote::Double),
+                Part::Text(r#"""#),
+            ]
+        );
+    }
+
+    #[test]
+    fn escape_for_quotes() {
+        assert_eq!(Quote::None.escape("it's"), r"'it'\''s'");
+        assert_eq!(Quote::Single.escape("it's"), r"it'\''s");
+        assert_eq!(Quote::Double.escape(r#"$a "b""#), r#"\$a \"b\""#);
+    }
+}
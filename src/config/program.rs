@@ -0,0 +1,307 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use toml::Spanned;
+
+use crate::command_line::{Arg, ArgOrder, ArgValue, CommandLine};
+use crate::data::{self, Button, Group, Page, PageAction, Program, ShellContext};
+use crate::ui::Context;
+
+use super::{default_true, CommandArg, Diagnostic, KeyEntry, Predicate, Source, ValueSource};
+
+/// A program menu loaded from a toml file, mirroring the [`Program`] built
+/// in rust by [`data::jj`].
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProgramDef {
+    #[serde(skip)]
+    pub name: String,
+    /// Keys this program binds on the home page.
+    #[serde(skip)]
+    pub home_keys: Vec<KeyEntry>,
+    key: Spanned<String>,
+    desc: String,
+    base: Vec<String>,
+    /// Shell command whose output is shown above the menu.
+    status: Option<String>,
+    #[serde(default = "default_true")]
+    show_by_default: bool,
+    #[serde(default)]
+    groups: Vec<GroupDef>,
+    /// Only show the program on the home page when this holds.
+    when: Option<Predicate>,
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct GroupDef {
+    desc: String,
+    #[serde(default)]
+    buttons: Vec<ButtonDef>,
+}
+
+/// Exactly one of `flag`, `subcommand` or `exec` must be set.
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ButtonDef {
+    key: Spanned<String>,
+    desc: String,
+
+    flag: Option<String>,
+    value: Option<ValueSource>,
+
+    subcommand: Option<Vec<String>>,
+    #[serde(default)]
+    groups: Vec<GroupDef>,
+    #[serde(default)]
+    args: Vec<ButtonDef>,
+    #[serde(default)]
+    actions: Vec<ButtonDef>,
+
+    exec: Option<Vec<String>>,
+    prompt: Option<PromptDef>,
+    #[serde(default)]
+    then: PageAction,
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PromptDef {
+    value: ValueSource,
+    /// Prepended to every value, e.g. `--revision=`. Values are passed as
+    /// positional arguments without it.
+    #[serde(default)]
+    prefix: String,
+}
+
+impl ProgramDef {
+    /// Parses and checks the program at `path`, named after the file.
+    pub fn load(path: &Path) -> (Option<ProgramDef>, Vec<Diagnostic>) {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) => return (None, vec![Diagnostic::read_error(path, e)]),
+        };
+        let mut source = Source::new(path, &text);
+        let def = source.parse::<ProgramDef>().and_then(|mut def| {
+            if def.base.is_empty() {
+                source.error(def.key.span(), "`base` must not be empty");
+                return None;
+            }
+            def.name = path.file_stem()?.to_string_lossy().into_owned();
+            def.home_keys = vec![source.key_entry(&def.key)];
+            source.check_keys(def.groups.iter().flat_map(|x| &x.buttons).map(|x| &x.key));
+            for group in &def.groups {
+                group.check(&mut source);
+            }
+            Some(def)
+        });
+        (def, source.diagnostics)
+    }
+
+    /// Toml files in `dir`, sorted by name.
+    pub fn paths(dir: impl AsRef<Path>) -> Vec<PathBuf> {
+        let Ok(entries) = fs::read_dir(dir) else {
+            return Vec::new();
+        };
+        let mut paths = entries
+            .filter_map(|entry| Some(entry.ok()?.path()))
+            .filter(|path| path.extension().is_some_and(|x| x == "toml"))
+            .collect::<Vec<_>>();
+        paths.sort();
+        paths
+    }
+
+    fn base_args(&self) -> Vec<Arg> {
+        let mut args = vec![Arg::program(&self.base[0])];
+        if self.base.len() > 1 {
+            args.push(Arg::new(
+                ArgOrder::PROGRAM,
+                ArgValue::Multi(self.base[1..].to_vec()),
+            ));
+        }
+        args
+    }
+
+    pub fn start_page(&self) -> Page {
+        let mut page = data::page(self.groups.iter().map(|x| x.group(0)).collect::<Vec<_>>())
+            .show_by_default(self.show_by_default);
+        if !self.show_by_default {
+            if let Some(group) = page.groups.last_mut() {
+                group
+                    .buttons
+                    .push(data::hidden_button("?", |mut ctx| ctx.toggle_show_pages()));
+            }
+        }
+        page
+    }
+
+    pub fn program(&self) -> Program {
+        let program = Program::new(CommandLine::from_iter(self.base_args()), self.start_page());
+        let Some(status) = self.status.clone() else {
+            return program;
+        };
+        program.with_status(move || {
+            let output = Command::new("bash").arg("-c").arg(&status).output()?;
+            Ok(String::from_utf8(output.stdout)?)
+        })
+    }
+
+    pub fn visible(&self, shell: &ShellContext) -> bool {
+        self.when.as_ref().map_or(true, |x| shell.check(x))
+    }
+
+    /// The button of an untrusted program, shown without evaluating `when`.
+    pub fn disabled_button(&self) -> Button {
+        Button {
+            disabled: true,
+            ..data::button(self.key.get_ref(), &self.desc, |_| Ok(()))
+        }
+    }
+
+    /// Button opening this program from another page.
+    pub fn button(&self) -> Button {
+        data::page_button(
+            self.key.get_ref(),
+            &self.desc,
+            self.base_args(),
+            self.start_page(),
+        )
+    }
+}
+
+impl GroupDef {
+    fn check(&self, source: &mut Source) {
+        for button in &self.buttons {
+            button.check(source);
+        }
+    }
+
+    fn group(&self, depth: u64) -> Group {
+        data::group(&self.desc, buttons(&self.buttons, depth))
+    }
+}
+
+fn buttons(defs: &[ButtonDef], depth: u64) -> Vec<Button> {
+    defs.iter().map(|x| x.button(depth)).collect()
+}
+
+impl ButtonDef {
+    fn check(&self, source: &mut Source) {
+        let span = self.key.span();
+        let kinds = [
+            self.flag.is_some(),
+            self.subcommand.is_some(),
+            self.exec.is_some(),
+        ];
+        if kinds.iter().filter(|x| **x).count() != 1 {
+            source.error(
+                span.clone(),
+                "needs exactly one of `flag`, `subcommand` or `exec`",
+            );
+        }
+        if self.value.is_some() && self.flag.is_none() {
+            source.error(span.clone(), "`value` is only allowed with `flag`");
+        }
+        if self.prompt.is_some() && self.exec.is_none() {
+            source.error(span.clone(), "`prompt` is only allowed with `exec`");
+        }
+        if self.subcommand.is_none() {
+            if !(self.groups.is_empty() && self.args.is_empty() && self.actions.is_empty()) {
+                source.error(
+                    span,
+                    "`groups`, `args` and `actions` are only allowed with `subcommand`",
+                );
+            }
+            return;
+        }
+        if self.groups.is_empty() {
+            source.check_keys(self.args.iter().chain(&self.actions).map(|x| &x.key));
+        } else {
+            if !(self.args.is_empty() && self.actions.is_empty()) {
+                source.error(span, "`groups` can not be used with `args` or `actions`");
+            }
+            source.check_keys(self.groups.iter().flat_map(|x| &x.buttons).map(|x| &x.key));
+        }
+        for group in &self.groups {
+            group.check(source);
+        }
+        for button in self.args.iter().chain(&self.actions) {
+            button.check(source);
+        }
+    }
+
+    /// `depth` is the number of subcommand pages above this button, used to
+    /// order subcommands after the ones already on the command line.
+    fn button(&self, depth: u64) -> Button {
+        if let Some(flag) = &self.flag {
+            let arg = CommandArg {
+                key: self.key.clone(),
+                desc: self.desc.clone(),
+                flag: flag.clone(),
+                value: self.value.clone(),
+            };
+            return arg.button();
+        }
+
+        if let Some(subcommand) = &self.subcommand {
+            let page = if self.groups.is_empty() {
+                data::args_page(
+                    buttons(&self.args, depth + 1),
+                    buttons(&self.actions, depth + 1),
+                )
+            } else {
+                data::page(
+                    self.groups
+                        .iter()
+                        .map(|x| x.group(depth + 1))
+                        .collect::<Vec<_>>(),
+                )
+            };
+            let arg = Arg::new(
+                ArgOrder::subcommand(depth),
+                ArgValue::Multi(subcommand.clone()),
+            );
+            return data::page_button(self.key.get_ref(), &self.desc, [arg], page);
+        }
+
+        let exec = self.exec.clone().unwrap_or_default();
+        let args = if exec.is_empty() {
+            vec![]
+        } else {
+            vec![Arg::new(ArgOrder::subcommand(depth), ArgValue::Multi(exec))]
+        };
+        match &self.prompt {
+            None => data::exec_button(self.key.get_ref(), &self.desc, args, self.then.clone()),
+            Some(prompt) => {
+                let prompt = prompt.clone();
+                let message = self.desc.clone();
+                data::exec_button_arg_prompt(
+                    self.key.get_ref(),
+                    &self.desc,
+                    args,
+                    self.then.clone(),
+                    move |ctx| prompt.args(ctx, &message),
+                )
+            }
+        }
+    }
+}
+
+impl PromptDef {
+    fn args(&self, ctx: &mut Context, message: &str) -> anyhow::Result<Vec<Arg>> {
+        let values = self.value.read(ctx, message)?;
+        Ok(values
+            .into_iter()
+            .map(|value| {
+                if self.prefix.is_empty() {
+                    Arg::positional(value)
+                } else {
+                    Arg::switch(format!("{}{value}", self.prefix))
+                }
+            })
+            .collect())
+    }
+}
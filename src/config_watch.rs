@@ -0,0 +1,124 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use tokio::task::JoinHandle;
+
+use crate::config::Level;
+use crate::data;
+use crate::ui::ExternalContext;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+const STATUS_DURATION: Duration = Duration::from_secs(3);
+
+/// Polls the config files and asks the ui to reload when they change.
+#[derive(Debug)]
+pub struct ConfigWatcher {
+    join_handle: JoinHandle<()>,
+}
+
+#[derive(PartialEq, Eq)]
+struct Snapshot {
+    cwd: Option<PathBuf>,
+    files: Vec<(PathBuf, Option<SystemTime>)>,
+}
+
+impl ConfigWatcher {
+    pub fn new(ctx: ExternalContext) -> Self {
+        let join_handle = tokio::spawn(async move {
+            let mut includes = Self::included_paths().await;
+            let mut last = Snapshot::take(includes.clone()).await;
+            loop {
+                tokio::time::sleep(POLL_INTERVAL).await;
+                let current = Snapshot::take(includes.clone()).await;
+                if current == last {
+                    continue;
+                }
+                // changing directory rebuilds the home page by itself
+                let moved = current.as_ref().map(|x| &x.cwd) != last.as_ref().map(|x| &x.cwd);
+                includes = Self::included_paths().await;
+                last = Snapshot::take(includes.clone()).await;
+                if !moved {
+                    Self::reload(ctx.clone()).await;
+                }
+            }
+        });
+        Self { join_handle }
+    }
+
+    async fn included_paths() -> Vec<PathBuf> {
+        tokio::task::spawn_blocking(data::included_paths)
+            .await
+            .unwrap_or_default()
+    }
+
+    async fn reload(ctx: ExternalContext) {
+        ctx.request_reload().await;
+        let errors = tokio::task::spawn_blocking(data::config_diagnostics)
+            .await
+            .map_or(0, |diagnostics| {
+                diagnostics
+                    .iter()
+                    .filter(|x| x.level == Level::Error)
+                    .count()
+            });
+        let message = match errors {
+            0 => "config reloaded".to_owned(),
+            1 => "config has 1 error".to_owned(),
+            n => format!("config has {n} errors"),
+        };
+        tokio::spawn(async move {
+            let id = ctx.begin_status(message).await;
+            tokio::time::sleep(STATUS_DURATION).await;
+            ctx.remove_status(id).await;
+        });
+    }
+}
+
+impl Snapshot {
+    /// `None` when the blocking task failed.
+    async fn take(includes: Vec<PathBuf>) -> Option<Self> {
+        tokio::task::spawn_blocking(move || Self::read(includes))
+            .await
+            .ok()
+    }
+
+    fn read(includes: Vec<PathBuf>) -> Self {
+        let mut files = Vec::new();
+        for path in data::config_paths().into_iter().chain(includes) {
+            match fs::read_dir(&path) {
+                Ok(entries) => {
+                    let mut entries = entries
+                        .filter_map(|entry| Some(entry.ok()?.path()))
+                        .map(|path| {
+                            let modified = modified(&path);
+                            (path, modified)
+                        })
+                        .collect::<Vec<_>>();
+                    entries.sort();
+                    files.extend(entries);
+                }
+                Err(_) => {
+                    let modified = modified(&path);
+                    files.push((path, modified));
+                }
+            }
+        }
+        Self {
+            cwd: std::env::current_dir().ok(),
+            files,
+        }
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).ok()?.modified().ok()
+}
+
+impl Drop for ConfigWatcher {
+    fn drop(&mut self) {
+        self.join_handle.abort()
+    }
+}
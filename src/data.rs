@@ -1,16 +1,27 @@
+mod palette;
 mod shell_context;
 
 use std::borrow::Cow;
+use std::collections::HashSet;
+use std::io::Write;
 use std::process::Command;
 
 use std::{process::Stdio, sync::Arc};
 
-use anyhow::Result;
+use anyhow::{bail, Context as _, Result};
 
 use crate::command_line::{Arg, ArgOrder, ArgValue, CommandLine};
+use crate::config::{check_keys, Diagnostic, KeyEntry, KeyMap, Level, Predicate};
+use crate::editor::Editor;
+use crate::trust;
 use crate::ui::Context;
+use crate::util::shell_quote;
 
-use self::shell_context::ShellContext;
+pub use self::jj::{jj_select_branch, jj_select_rev, RevSelector};
+pub use self::palette::palette;
+pub use self::shell_context::set_user_config_path;
+pub use self::shell_context::{clear_predicate_cache, config_paths, included_paths};
+pub use self::shell_context::{ShellContext, UntrustedConfig};
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Keybind(pub String);
@@ -21,6 +32,8 @@ pub struct Button {
     pub description: String,
     pub handler: Arc<dyn ButtonHandler>,
     pub hidden: bool,
+    /// Shown dimmed and not bound to its key.
+    pub disabled: bool,
 }
 
 #[derive(Debug, Clone)]
@@ -33,6 +46,8 @@ pub struct Group {
 pub struct Page {
     pub groups: Vec<Group>,
     pub show_by_default: bool,
+    /// Messages shown above the groups, e.g. config errors.
+    pub banner: Vec<String>,
 }
 
 #[derive(Clone)]
@@ -41,6 +56,8 @@ pub struct Program {
     pub start: Page,
     pub status: Option<Arc<dyn Fn() -> anyhow::Result<String>>>,
     pub status_cache: Option<String>,
+    /// Rebuilds the start page when the config changes.
+    pub reload: Option<Arc<dyn Fn() -> anyhow::Result<Page>>>,
 }
 
 pub enum ButtonValue<'a> {
@@ -89,6 +106,7 @@ impl Program {
             start,
             status: None,
             status_cache: None,
+            reload: None,
         }
     }
 
@@ -100,6 +118,14 @@ impl Program {
         self
     }
 
+    pub fn with_reload<F>(mut self, reload: F) -> Self
+    where
+        F: Fn() -> anyhow::Result<Page> + 'static,
+    {
+        self.reload = Some(Arc::new(reload));
+        self
+    }
+
     pub fn refresh_status(&mut self) -> anyhow::Result<()> {
         self.status_cache = self.status.as_ref().map(|x| x()).transpose()?;
         Ok(())
@@ -115,6 +141,7 @@ impl Page {
         Self {
             groups: Vec::new(),
             show_by_default: false,
+            banner: Vec::new(),
         }
     }
 
@@ -142,7 +169,7 @@ fn select_branch(extra_args: &str) -> anyhow::Result<String> {
     Ok(output_text)
 }
 
-fn select_directory() -> anyhow::Result<String> {
+pub fn select_directory() -> anyhow::Result<String> {
     let output = std::process::Command::new("bash")
         .arg("-c")
         .arg(r#"cat <(zoxide query -l) <(fd --follow --maxdepth 3 -t d . "$PWD") | sed "s:/$::" | awk '!seen[$0]++' | fzf --tiebreak=end,index"#)
@@ -154,6 +181,37 @@ fn select_directory() -> anyhow::Result<String> {
     Ok(output_text)
 }
 
+pub fn select_from_command(command: &str, prompt: &str) -> anyhow::Result<Vec<String>> {
+    let output = std::process::Command::new("bash")
+        .arg("-c")
+        .arg(format!(
+            "{command} | fzf --multi --prompt {}",
+            shell_quote(&format!("{prompt}: "))
+        ))
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .output()?;
+    let output_text = String::from_utf8(output.stdout)?;
+    Ok(output_text.lines().map(|x| x.to_string()).collect())
+}
+
+pub fn select_from_list(items: &[String], prompt: &str) -> anyhow::Result<Vec<String>> {
+    let mut child = std::process::Command::new("fzf")
+        .arg("--multi")
+        .arg("--prompt")
+        .arg(format!("{prompt}: "))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .spawn()?;
+    let mut stdin = child.stdin.take().context("fzf stdin")?;
+    stdin.write_all(items.join("\n").as_bytes())?;
+    drop(stdin);
+    let output = child.wait_with_output()?;
+    let output_text = String::from_utf8(output.stdout)?;
+    Ok(output_text.lines().map(|x| x.to_string()).collect())
+}
+
 pub struct ToggleFlag(pub Cow<'static, str>);
 
 impl ButtonHandler for ToggleFlag {
@@ -175,8 +233,10 @@ impl ButtonHandler for ToggleFlag {
     }
 }
 
+type PromptFn = dyn Fn(&mut Context<'_, '_>) -> anyhow::Result<Vec<String>>;
+
 pub struct PromptButton {
-    f: Box<dyn Fn(&mut Context<'_, '_>) -> anyhow::Result<Vec<String>>>,
+    f: Box<PromptFn>,
     arg: String,
 }
 
@@ -187,7 +247,9 @@ impl ButtonHandler for PromptButton {
             self.unset_value(ctx.command_line_mut());
         } else {
             let value = (self.f)(&mut ctx)?;
-            self.set_values(ctx.command_line_mut(), value);
+            if !value.is_empty() {
+                self.set_values(ctx.command_line_mut(), value);
+            }
         }
 
         Ok(())
@@ -248,6 +310,7 @@ pub fn page(groups: impl Into<Vec<Group>>) -> Page {
     Page {
         groups: groups.into(),
         show_by_default: true,
+        banner: Vec::new(),
     }
 }
 
@@ -268,9 +331,11 @@ pub fn button(
         description: description.into(),
         handler: Arc::new(handler),
         hidden: false,
+        disabled: false,
     }
 }
 
+#[allow(clippy::arc_with_non_send_sync)]
 pub fn prompt_button(
     key: impl Into<String>,
     description: impl Into<String>,
@@ -285,15 +350,21 @@ pub fn prompt_button(
             arg: name.to_string(),
         }),
         hidden: false,
+        disabled: false,
     }
 }
 
-pub fn flag_button(key: &'static str, description: &str, flag: &'static str) -> Button {
+pub fn flag_button(
+    key: impl Into<String>,
+    description: impl Into<String>,
+    flag: impl Into<Cow<'static, str>>,
+) -> Button {
     Button {
         key: Keybind(key.into()),
         description: description.into(),
-        handler: Arc::new(ToggleFlag(Cow::Borrowed(flag))),
+        handler: Arc::new(ToggleFlag(flag.into())),
         hidden: false,
+        disabled: false,
     }
 }
 
@@ -306,30 +377,233 @@ pub fn hidden_button(
         description: String::new(),
         handler: Arc::new(handler),
         hidden: true,
+        disabled: false,
     }
 }
 
 pub fn jj() -> anyhow::Result<Program> {
-    let start = jj::jj()?;
+    let start = ShellContext::new().keys().apply_page("jj", jj::jj()?);
     Ok(
         Program::new(CommandLine::from_iter([Arg::program("jj")]), start)
             .with_status(jj::jj_status),
     )
 }
 
+/// The program opened at startup: the home page, `jj`, or a program
+/// definition by name.
+pub fn program(name: Option<&str>) -> anyhow::Result<Program> {
+    match name {
+        None => top(),
+        Some("jj") => jj(),
+        Some(name) => ShellContext::new()
+            .programs()
+            .iter()
+            .find(|x| x.name == name)
+            .map(|x| x.program())
+            .with_context(|| format!("unknown program `{name}`")),
+    }
+}
+
 pub fn top() -> anyhow::Result<Program> {
     let start = home_page()?;
-    Ok(Program::new(CommandLine::from_iter([]), start))
+    Ok(Program::new(CommandLine::from_iter([]), start).with_reload(home_page))
 }
 
 fn home_page() -> Result<Page, anyhow::Error> {
     let shell_context = ShellContext::new();
-    let mut builtin_buttons = vec![
-        button("c", "Change Directory", |mut ctx| {
-            ctx.change_dir(select_directory()?)?;
-            ctx.replace_page(home_page()?);
-            Ok(())
-        }),
+    let builtin_buttons = builtin_buttons(&shell_context)?;
+    let diagnostics = home_diagnostics(&shell_context, &builtin_buttons);
+    let mut page = page([group("Builtin commands", builtin_buttons)]);
+    let programs = shell_context
+        .programs()
+        .iter()
+        .filter(|x| x.visible(&shell_context))
+        .collect::<Vec<_>>();
+    if !programs.is_empty() {
+        page.add_group(group(
+            "Programs",
+            programs.iter().map(|x| x.button()).collect::<Vec<_>>(),
+        ));
+    }
+    if let Some(config) = shell_context.user_config() {
+        page.add_group(group(
+            "User commands",
+            config.command_buttons(&shell_context),
+        ));
+    }
+
+    if let Some(config) = shell_context.project_config() {
+        page.add_group(group(
+            "Project commands",
+            config.command_buttons(&shell_context),
+        ));
+    }
+
+    let untrusted = shell_context.untrusted_configs();
+    for (i, untrusted_config) in untrusted.iter().enumerate() {
+        let key = trust_key(i, untrusted.len());
+        let mut buttons = untrusted_config.config.disabled_buttons();
+        buttons.extend(
+            untrusted_config
+                .programs
+                .iter()
+                .map(|x| x.disabled_button()),
+        );
+        buttons.push(trust_button(key, untrusted_config));
+        page.add_group(group(
+            format!(
+                "Untrusted project commands ({})",
+                untrusted_config.path.display()
+            ),
+            buttons,
+        ));
+    }
+    if let Some(location) = diagnostics.iter().find_map(|x| x.location.clone()) {
+        page.add_group(group(
+            "Config problems",
+            [button("E", "Edit the first problem", move |mut ctx| {
+                ctx.leave_ui()?;
+                let editor = Editor::resolve(&ShellContext::new());
+                ctx.run_command_new_term(&mut editor.open_location(&location))?;
+                Ok(())
+            })],
+        ));
+    }
+    page.banner = diagnostics
+        .iter()
+        .flat_map(|x| x.to_string().lines().map(str::to_owned).collect::<Vec<_>>())
+        .collect();
+    Ok(page)
+}
+
+fn trust_key(index: usize, count: usize) -> String {
+    if count == 1 {
+        "T".to_owned()
+    } else {
+        format!("T{}", index + 1)
+    }
+}
+
+/// Shows the config and the files trusted with it, and trusts them if the
+/// user agrees.
+fn trust_button(key: String, untrusted: &UntrustedConfig) -> Button {
+    let path = untrusted.path.clone();
+    let included = untrusted.files.clone();
+    button(key, "Review and trust", move |mut ctx| {
+        // the pager is not run by `--keys` and `--emit`, so nothing would be
+        // reviewed
+        if !ctx.is_interactive() {
+            bail!("configs can only be trusted interactively");
+        }
+        ctx.leave_ui()?;
+        let pager = std::env::var("PAGER")
+            .ok()
+            .filter(|x| !x.trim().is_empty())
+            .unwrap_or("less".to_owned());
+        let files = [&path].into_iter().chain(&included).filter(|x| x.exists());
+        // `$PAGER` may have arguments, like `less -R`
+        ctx.run_command_in_foreground(
+            Command::new("sh")
+                .arg("-c")
+                .arg(format!("{pager} \"$@\""))
+                .arg("sh")
+                .args(files),
+        )?;
+        let answer = ctx.read_input(&format!("Trust {}? [y/N]", path.display()))?;
+        if answer.trim().eq_ignore_ascii_case("y") {
+            trust::trust(&path, &included)?;
+            ctx.replace_root_page(home_page()?);
+        }
+        Ok(())
+    })
+}
+
+/// Problems in the user and project configs, including key conflicts between
+/// everything shown on the home page.
+pub fn config_diagnostics() -> Vec<Diagnostic> {
+    let shell_context = ShellContext::new();
+    let builtin_buttons = builtin_buttons(&shell_context).unwrap_or_default();
+    home_diagnostics(&shell_context, &builtin_buttons)
+}
+
+fn home_diagnostics(shell_context: &ShellContext, builtin_buttons: &[Button]) -> Vec<Diagnostic> {
+    let key_map = shell_context.keys();
+    let mut keys = builtin_buttons
+        .iter()
+        .map(|x| key_map.key_entry("home", x))
+        .collect::<Vec<_>>();
+    for program in shell_context.programs() {
+        if program.visible(shell_context) {
+            keys.extend(program.home_keys.iter().cloned());
+        }
+    }
+    for config in [shell_context.user_config(), shell_context.project_config()]
+        .into_iter()
+        .flatten()
+    {
+        keys.extend(config.home_keys(shell_context).cloned());
+    }
+    let untrusted = shell_context.untrusted_configs();
+    let mut diagnostics = shell_context.diagnostics();
+    for (i, untrusted_config) in untrusted.iter().enumerate() {
+        let key = trust_key(i, untrusted.len());
+        diagnostics.push(Diagnostic {
+            level: Level::Warning,
+            location: None,
+            message: format!(
+                "{} is not trusted, review it with `{key}` on the home page",
+                untrusted_config.path.display()
+            ),
+            included_from: Vec::new(),
+        });
+        keys.push(KeyEntry {
+            key,
+            location: None,
+        });
+    }
+    check_keys(&keys, &mut diagnostics);
+    if let (Ok(jj_page), Ok(buttons)) = (jj::jj(), all_builtin_buttons(&key_map)) {
+        let home_page = page([group(
+            "",
+            buttons.into_iter().map(|x| x.1).collect::<Vec<_>>(),
+        )]);
+        key_map.check_paths(
+            &[("home", home_page), ("jj", jj_page.clone())],
+            &mut diagnostics,
+        );
+        key_map.check_page("jj", &key_map.apply_page("jj", jj_page), &mut diagnostics);
+    }
+    // conflicts within one file are also reported when the file is checked
+    let mut seen = HashSet::new();
+    diagnostics.retain(|x| {
+        let location = x.location.as_ref().map(ToString::to_string);
+        seen.insert((location, x.message.clone()))
+    });
+    diagnostics
+}
+
+fn builtin_buttons(shell_context: &ShellContext) -> anyhow::Result<Vec<Button>> {
+    let key_map = shell_context.keys();
+    let buttons = all_builtin_buttons(&key_map)?
+        .into_iter()
+        .filter(|(when, _)| when.as_ref().map_or(true, |x| shell_context.check(x)))
+        .map(|(_, button)| button)
+        .collect();
+    Ok(key_map.apply("home", buttons))
+}
+
+/// Every builtin button on the home page with the condition for showing it,
+/// before remapping keys.
+fn all_builtin_buttons(key_map: &KeyMap) -> anyhow::Result<Vec<(Option<Predicate>, Button)>> {
+    Ok(vec![
+        (
+            None,
+            button("c", "Change Directory", |mut ctx| {
+                ctx.change_dir(select_directory()?)?;
+                ctx.replace_page(home_page()?);
+                Ok(())
+            }),
+        ),
         // button("S", "Shell Command", |mut ctx| {
         //     // TODO: run shell commmand from history
         //     let input = ctx.read_input()?;
@@ -340,40 +614,41 @@ fn home_page() -> Result<Page, anyhow::Error> {
         //     ctx.run_command_in_foreground(&mut Command::new(shell).arg("-c").arg(input))?;
         //     Ok(())
         // }),
-        button("s", "Shell", |mut ctx| {
-            ctx.leave_ui()?;
-            let shell = std::env::var("SHELL").unwrap_or("bash".to_owned());
-            ctx.run_command_in_foreground(&mut Command::new(shell))?;
-            Ok(())
-        }),
-    ];
-    if shell_context.is_git() {
-        builtin_buttons.push(button("j", "Jujutsu", |mut ctx| {
-            ctx.push_page(jj::jj()?);
-            ctx.command_line_mut().add_arg(Arg::program("jj"));
-            Ok(())
-        }));
-        builtin_buttons.push(button("e", "Edit", |mut ctx| {
-            ctx.leave_ui()?;
-            // TODO: handle EDITOR
-            ctx.run_command_new_term(Command::new("hx").arg("."))?;
-            Ok(())
-        }));
-    }
-    let mut page = page([group("Builtin commands", builtin_buttons)]);
-    if let Some(config) = shell_context.user_config() {
-        page.add_group(group("User commands", config.command_buttons()));
-    }
-
-    if let Some(config) = shell_context.project_config() {
-        page.add_group(group("Project commands", config.command_buttons()));
-    }
-    Ok(page)
-}
-
-#[derive(Debug, Clone, PartialEq, Eq)]
+        (
+            None,
+            button("s", "Shell", |mut ctx| {
+                ctx.leave_ui()?;
+                let shell = std::env::var("SHELL").unwrap_or("bash".to_owned());
+                ctx.run_command_in_foreground(&mut Command::new(shell))?;
+                Ok(())
+            }),
+        ),
+        (
+            Some(Predicate::Any(vec![Predicate::Jj, Predicate::Git])),
+            page_button(
+                "j",
+                "Jujutsu",
+                [Arg::program("jj")],
+                key_map.apply_page("jj", jj::jj()?),
+            ),
+        ),
+        (
+            Some(Predicate::Git),
+            button("e", "Edit", |mut ctx| {
+                ctx.leave_ui()?;
+                let editor = Editor::resolve(&ShellContext::new());
+                ctx.run_command_new_term(&mut editor.open("."))?;
+                Ok(())
+            }),
+        ),
+    ])
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum PageAction {
     Pop,
+    #[default]
     None,
 }
 
@@ -397,20 +672,55 @@ pub fn prompt_arg(
     Ok(())
 }
 
+/// Opens `page` and adds `args` to the command line. A type rather than a
+/// closure so the page tree can be walked.
+pub struct SubPage {
+    pub page: Page,
+    pub args: Vec<Arg>,
+}
+
+impl ButtonHandler for SubPage {
+    fn run(&self, mut ctx: Context<'_, '_>) -> anyhow::Result<()> {
+        ctx.push_page(self.page.clone());
+        for arg in &self.args {
+            ctx.command_line_mut().add_arg(arg.clone());
+        }
+        Ok(())
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self as _
+    }
+}
+
+#[allow(clippy::arc_with_non_send_sync)]
+pub fn page_button(
+    key: impl Into<String>,
+    description: impl Into<String>,
+    args: impl IntoIterator<Item = Arg>,
+    page: Page,
+) -> Button {
+    Button {
+        key: Keybind(key.into()),
+        description: description.into(),
+        handler: Arc::new(SubPage {
+            page,
+            args: args.into_iter().collect(),
+        }),
+        hidden: false,
+        disabled: false,
+    }
+}
+
 pub fn subcommand_button<I>(key: &'static str, description: &str, args: I, page: Page) -> Button
 where
     I: IntoIterator<Item = &'static str>,
 {
-    let arg = Arg::subcommands(args);
-    button(key, description, move |mut ctx| {
-        ctx.push_page(page.clone());
-        ctx.command_line_mut().add_arg(arg.clone());
-        Ok(())
-    })
+    page_button(key, description, [Arg::subcommands(args)], page)
 }
 
 pub fn exec_button(
-    key: &'static str,
+    key: impl Into<String>,
     description: &str,
     args: impl IntoIterator<Item = Arg>,
     page_action: PageAction,
@@ -429,7 +739,7 @@ pub fn exec_button(
 }
 
 pub fn exec_button_arg_prompt(
-    key: &'static str,
+    key: impl Into<String>,
     description: &str,
     args: impl IntoIterator<Item = Arg>,
     page_action: PageAction,
@@ -456,7 +766,7 @@ pub fn exec_button_arg_prompt(
 }
 
 pub fn exec_button_arg_prompt2(
-    key: &'static str,
+    key: impl Into<String>,
     description: &str,
     args: impl IntoIterator<Item = Arg>,
     page_action: PageAction,
@@ -27,13 +27,13 @@ fn shell_cmd(bash_cmd: impl Into<String>) -> Command {
 }
 
 #[derive(Clone, Copy, Debug)]
-enum RevSelector {
+pub enum RevSelector {
     All,
     Mutable,
     NotInTrunk,
 }
 
-fn jj_select_rev(
+pub fn jj_select_rev(
     arg: &'static str,
     revs: RevSelector,
 ) -> impl Fn(&mut Context) -> anyhow::Result<Vec<String>> {
@@ -64,7 +64,7 @@ fn jj_select_rev(
     }
 }
 
-fn jj_select_branch(arg: &'static str) -> impl Fn(&mut Context) -> anyhow::Result<Vec<String>> {
+pub fn jj_select_branch(arg: &'static str) -> impl Fn(&mut Context) -> anyhow::Result<Vec<String>> {
     move |ctx| {
         ctx.leave_ui()?;
         let output = shell_cmd(format!(
@@ -0,0 +1,80 @@
+use std::io::Write;
+use std::process::Stdio;
+
+use anyhow::Context as _;
+
+use super::{Page, SubPage};
+use crate::ui::Context;
+
+/// Pages nested deeper than this are left out.
+const MAX_DEPTH: usize = 8;
+
+/// A button somewhere in the page tree.
+struct Entry {
+    /// The keys that reach and press it from the first page.
+    keys: String,
+    /// Descriptions of the pages on the way and the button itself.
+    path: Vec<String>,
+}
+
+/// Every visible button of `page` and the pages it opens, depth first.
+fn entries(page: &Page) -> Vec<Entry> {
+    let mut entries = Vec::new();
+    collect(page, "", &[], &mut entries);
+    entries
+}
+
+fn collect(page: &Page, keys: &str, path: &[String], entries: &mut Vec<Entry>) {
+    if path.len() > MAX_DEPTH {
+        return;
+    }
+    for button in page.groups.iter().flat_map(|x| &x.buttons) {
+        if button.hidden || button.disabled {
+            continue;
+        }
+        let keys = format!("{keys}{}", button.key.0);
+        let mut path = path.to_vec();
+        path.push(button.description.clone());
+        entries.push(Entry {
+            keys: keys.clone(),
+            path: path.clone(),
+        });
+        if let Some(sub_page) = button.handler.as_any().downcast_ref::<SubPage>() {
+            collect(&sub_page.page, &keys, &path, entries);
+        }
+    }
+}
+
+/// Picks a button from the whole page tree with fzf and presses the keys
+/// that lead to it, starting from the first page.
+pub fn palette(mut ctx: Context) -> anyhow::Result<()> {
+    let entries = entries(ctx.root_page());
+    let width = entries.iter().map(|x| x.keys.len()).max().unwrap_or(0);
+    let lines = entries
+        .iter()
+        .enumerate()
+        .map(|(i, x)| format!("{i}\t{:width$}  {}", x.keys, x.path.join(" > ")))
+        .collect::<Vec<_>>();
+    ctx.leave_ui()?;
+    let mut child = std::process::Command::new("fzf")
+        .args(["--delimiter=\t", "--with-nth=2..", "--prompt=Command: "])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .spawn()
+        .context("running fzf")?;
+    let mut stdin = child.stdin.take().context("fzf stdin")?;
+    stdin.write_all(lines.join("\n").as_bytes())?;
+    drop(stdin);
+    let output = child.wait_with_output()?;
+    let output_text = String::from_utf8(output.stdout)?;
+    let Some(index) = output_text
+        .split('\t')
+        .next()
+        .and_then(|x| x.parse::<usize>().ok())
+    else {
+        return Ok(());
+    };
+    while ctx.pop_page() {}
+    ctx.press_keys(&entries[index].keys)
+}
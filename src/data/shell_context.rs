@@ -1,16 +1,42 @@
-use crate::config::Config;
+use crate::config::{Config, Diagnostic, KeyMap, Predicate, ProgramDef};
+use crate::trust;
 use anyhow::Context as _;
-use dirs;
+use once_cell::sync::Lazy;
 use once_cell::unsync::OnceCell;
-use std::cell::Cell;
-use std::path::Path;
-use std::process::Command;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
 
 pub struct ShellContext {
     user_config: OnceCell<Option<Config>>,
-    project_config: OnceCell<Option<Config>>,
+    project_config: OnceCell<ProjectConfigs>,
+    programs: OnceCell<Vec<ProgramDef>>,
     is_git: Cell<Option<bool>>,
     is_cp: Cell<Option<bool>>,
+    diagnostics: RefCell<Vec<Diagnostic>>,
+}
+
+/// A project config whose commands are not run until the user trusts it.
+pub struct UntrustedConfig {
+    pub path: PathBuf,
+    /// Empty when there are only program definitions.
+    pub config: Config,
+    /// Program definitions in `.humsh/programs` next to the config.
+    pub programs: Vec<ProgramDef>,
+    /// The files trusted together with the config: its includes and the
+    /// program definitions.
+    pub files: Vec<PathBuf>,
+}
+
+#[derive(Default)]
+struct ProjectConfigs {
+    /// The trusted configs merged.
+    config: Option<Config>,
+    /// Program definitions of the trusted project.
+    programs: Vec<ProgramDef>,
+    untrusted: Vec<UntrustedConfig>,
 }
 
 impl ShellContext {
@@ -18,8 +44,10 @@ impl ShellContext {
         Self {
             user_config: OnceCell::new(),
             project_config: OnceCell::new(),
+            programs: OnceCell::new(),
             is_git: Cell::new(None),
             is_cp: Cell::new(None),
+            diagnostics: RefCell::new(Vec::new()),
         }
     }
 
@@ -53,32 +81,222 @@ impl ShellContext {
         result
     }
 
+    /// Evaluates `predicate` in the current directory. Results are cached per
+    /// directory until [`clear_predicate_cache`].
+    pub fn check(&self, predicate: &Predicate) -> bool {
+        let key = std::env::current_dir().ok().map(|x| (x, predicate.clone()));
+        let cache = PREDICATES.lock().unwrap_or_else(|x| x.into_inner());
+        if let Some(result) = key.as_ref().and_then(|x| cache.get(x)) {
+            return *result;
+        }
+        // nested predicates lock it again
+        drop(cache);
+
+        let result = match predicate {
+            Predicate::Git => self.is_git(),
+            Predicate::Jj => detect_jj(),
+            Predicate::FileExists(path) => path.exists(),
+            Predicate::Env(name) => std::env::var_os(name).is_some_and(|x| !x.is_empty()),
+            Predicate::CommandSucceeds(command) => Command::new("bash")
+                .arg("-c")
+                .arg(command)
+                .stdin(Stdio::null())
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .status()
+                .is_ok_and(|x| x.success()),
+            Predicate::All(predicates) => predicates.iter().all(|x| self.check(x)),
+            Predicate::Any(predicates) => predicates.iter().any(|x| self.check(x)),
+            Predicate::Not(predicate) => !self.check(predicate),
+        };
+
+        if let Some(key) = key {
+            PREDICATES
+                .lock()
+                .unwrap_or_else(|x| x.into_inner())
+                .insert(key, result);
+        }
+        result
+    }
+
     pub fn user_config(&self) -> Option<&Config> {
         self.user_config
             .get_or_init(|| {
-                let path = dirs::config_dir()
-                    .context("config dir not found")
-                    .ok()?
-                    .join("humsh/config.toml");
+                let path = user_config_path()?;
                 if !path.exists() {
                     return None;
                 }
-                Config::read(&path).ok()
+                self.load(Config::load(&path))
             })
             .as_ref()
     }
 
+    /// The trusted project configs from the current directory up to the
+    /// repository root, merged with nearer ones taking precedence.
     pub fn project_config(&self) -> Option<&Config> {
-        self.project_config
-            .get_or_init(|| {
-                let path = Path::new(".humsh/config.toml");
-                if !path.exists() {
-                    return None;
+        self.project_configs().config.as_ref()
+    }
+
+    pub fn untrusted_configs(&self) -> &[UntrustedConfig] {
+        &self.project_configs().untrusted
+    }
+
+    fn project_configs(&self) -> &ProjectConfigs {
+        self.project_config.get_or_init(|| {
+            let mut trusted = Vec::new();
+            let mut configs = ProjectConfigs::default();
+            for (i, (path, dir)) in project_config_paths().into_iter().enumerate() {
+                // programs only come from the current directory
+                let program_paths = match i {
+                    0 => ProgramDef::paths(PROJECT_PROGRAMS),
+                    _ => Vec::new(),
+                };
+                let config = if path.exists() {
+                    let Some(mut config) = self.load(Config::load(&path)) else {
+                        continue;
+                    };
+                    config.set_dir(&dir);
+                    config
+                } else if !program_paths.is_empty() {
+                    Config::default()
+                } else {
+                    continue;
+                };
+                let mut files = config.included.clone();
+                files.extend(program_paths.iter().cloned());
+                let programs = self.load_programs(&program_paths);
+                if trust::is_trusted(&path, &files) {
+                    trusted.push(config);
+                    configs.programs.extend(programs);
+                } else {
+                    configs.untrusted.push(UntrustedConfig {
+                        path,
+                        config,
+                        programs,
+                        files,
+                    });
                 }
-                Config::read(&path).ok()
-            })
-            .as_ref()
+            }
+            configs.config = Config::merge(trusted);
+            configs
+        })
     }
+
+    /// Key remaps for the builtin menus.
+    pub fn keys(&self) -> KeyMap {
+        self.user_config()
+            .map(|x| x.keys.clone())
+            .unwrap_or_default()
+    }
+
+    /// Program definitions from the user `programs` directory and the one of
+    /// the project, when it is trusted.
+    pub fn programs(&self) -> &[ProgramDef] {
+        self.programs.get_or_init(|| {
+            let mut programs = user_program_dir()
+                .map(|dir| self.load_programs(&ProgramDef::paths(dir)))
+                .unwrap_or_default();
+            programs.extend(self.project_configs().programs.iter().cloned());
+            programs
+        })
+    }
+
+    fn load_programs(&self, paths: &[PathBuf]) -> Vec<ProgramDef> {
+        paths
+            .iter()
+            .filter_map(|path| self.load(ProgramDef::load(path)))
+            .collect()
+    }
+
+    fn load<T>(&self, (value, diagnostics): (Option<T>, Vec<Diagnostic>)) -> Option<T> {
+        self.diagnostics.borrow_mut().extend(diagnostics);
+        value
+    }
+
+    /// Problems found in the configs loaded so far.
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        self.diagnostics.borrow().clone()
+    }
+}
+
+/// Results of [`ShellContext::check`] by directory, shared by every context
+/// so `command_succeeds` predicates run once per directory.
+static PREDICATES: Lazy<Mutex<HashMap<(PathBuf, Predicate), bool>>> = Lazy::new(Default::default);
+
+/// Forgets the results of predicates, after changing directory.
+pub fn clear_predicate_cache() {
+    PREDICATES.lock().unwrap_or_else(|x| x.into_inner()).clear();
+}
+
+static USER_CONFIG_PATH: once_cell::sync::OnceCell<PathBuf> = once_cell::sync::OnceCell::new();
+
+/// Reads the user config from `path` instead of the config dir, for
+/// `--config`.
+pub fn set_user_config_path(path: PathBuf) {
+    USER_CONFIG_PATH.set(path).ok();
+}
+
+fn user_config_path() -> Option<PathBuf> {
+    if let Some(path) = USER_CONFIG_PATH.get() {
+        return Some(path.clone());
+    }
+    let dir = dirs::config_dir().context("config dir not found").ok()?;
+    Some(dir.join("humsh/config.toml"))
+}
+
+/// `.humsh/config.toml` in the current directory and each parent up to the
+/// repository root, nearest first, with the directory it belongs to. Outside
+/// a repository only the current directory is searched.
+fn project_config_paths() -> Vec<(PathBuf, PathBuf)> {
+    let Ok(cwd) = std::env::current_dir() else {
+        return Vec::new();
+    };
+    let is_root = |dir: &Path| dir.join(".git").exists() || dir.join(".jj").is_dir();
+    let depth = cwd.ancestors().position(is_root).unwrap_or(0);
+    // relative paths keep diagnostics short
+    let mut relative = PathBuf::new();
+    let mut paths = Vec::new();
+    for dir in cwd.ancestors().take(depth + 1) {
+        paths.push((relative.join(".humsh/config.toml"), dir.to_owned()));
+        relative.push("..");
+    }
+    paths
+}
+
+/// Program definitions of the project in the current directory, trusted
+/// together with its config.
+const PROJECT_PROGRAMS: &str = ".humsh/programs";
+
+fn user_program_dir() -> Option<PathBuf> {
+    Some(dirs::config_dir()?.join("humsh/programs"))
+}
+
+/// Files and directories the home page is built from, without the files
+/// the configs include.
+pub fn config_paths() -> Vec<PathBuf> {
+    let mut paths = Vec::new();
+    paths.extend(user_config_path());
+    paths.extend(project_config_paths().into_iter().map(|(path, _)| path));
+    paths.extend(user_program_dir());
+    paths.push(PathBuf::from(PROJECT_PROGRAMS));
+    paths
+}
+
+/// The files the configs include. This parses every config, so it is only
+/// worth redoing after they changed.
+pub fn included_paths() -> Vec<PathBuf> {
+    let mut paths = Vec::new();
+    let shell_context = ShellContext::new();
+    for config in [shell_context.user_config(), shell_context.project_config()]
+        .into_iter()
+        .flatten()
+    {
+        paths.extend(config.included.iter().cloned());
+    }
+    for untrusted in shell_context.untrusted_configs() {
+        paths.extend(untrusted.files.iter().cloned());
+    }
+    paths
 }
 
 fn detect_git() -> bool {
@@ -91,3 +309,10 @@ fn detect_git() -> bool {
         .map(|output| output.stdout.starts_with(b"true"))
         .unwrap_or(false)
 }
+
+fn detect_jj() -> bool {
+    let Ok(dir) = std::env::current_dir() else {
+        return false;
+    };
+    dir.ancestors().any(|x| x.join(".jj").is_dir())
+}
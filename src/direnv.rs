@@ -60,7 +60,7 @@ impl Direnv {
         }
     }
 
-    pub fn hook(&mut self, cmd: &mut std::process::Command) -> anyhow::Result<()> {
+    pub fn hook(&mut self, _cmd: &mut std::process::Command) -> anyhow::Result<()> {
         // let env = self.env()?;
         // cmd.envs(env.iter().cloned());
         Ok(())
@@ -0,0 +1,116 @@
+use std::path::Path;
+use std::process::Command;
+
+use crate::config::Location;
+use crate::data::ShellContext;
+use crate::util::shell_split;
+
+/// The user's editor, e.g. `nvim` or `code --wait`.
+#[derive(Debug, Clone)]
+pub struct Editor {
+    program: String,
+    args: Vec<String>,
+}
+
+/// How an editor is told to open a file at a position.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Flavor {
+    /// `hx file:line:col`
+    Helix,
+    /// `vi +line file`, plain vi has no way to set the column.
+    Vi,
+    /// `vim '+call cursor(line, col)' file`
+    Vim,
+    /// `emacs +line:col file`
+    Emacs,
+    /// `code --goto file:line:col`
+    VsCode,
+    /// Only the file.
+    Other,
+}
+
+impl Editor {
+    /// The `editor` option of the user config, then `$VISUAL`, then
+    /// `$EDITOR`, falling back to `vi`.
+    pub fn resolve(shell: &ShellContext) -> Editor {
+        shell
+            .user_config()
+            .and_then(|x| x.editor.as_deref())
+            .and_then(Editor::parse)
+            .or_else(|| Editor::parse(&std::env::var("VISUAL").ok()?))
+            .or_else(|| Editor::parse(&std::env::var("EDITOR").ok()?))
+            .unwrap_or_else(|| Editor {
+                program: "vi".into(),
+                args: Vec::new(),
+            })
+    }
+
+    /// Splits `command` into words like a shell, `None` if it is empty or a
+    /// quote is not closed.
+    pub fn parse(command: &str) -> Option<Editor> {
+        let mut words = shell_split(command)?.into_iter();
+        Some(Editor {
+            program: words.next()?,
+            args: words.collect(),
+        })
+    }
+
+    fn flavor(&self) -> Flavor {
+        let name = Path::new(&self.program)
+            .file_name()
+            .map_or_else(Default::default, |x| x.to_string_lossy());
+        match name.as_ref() {
+            "hx" | "helix" => Flavor::Helix,
+            "vi" => Flavor::Vi,
+            "vim" | "nvim" | "gvim" | "mvim" => Flavor::Vim,
+            "emacs" | "emacsclient" => Flavor::Emacs,
+            "code" | "code-insiders" | "codium" => Flavor::VsCode,
+            _ => Flavor::Other,
+        }
+    }
+
+    /// Command opening `path`, which may also be a directory.
+    pub fn open(&self, path: impl AsRef<Path>) -> Command {
+        let mut command = Command::new(&self.program);
+        command.args(&self.args).arg(path.as_ref());
+        command
+    }
+
+    /// Command opening `path` with the cursor at `line` and `column`, both
+    /// starting at 1.
+    pub fn open_at(&self, path: impl AsRef<Path>, line: usize, column: usize) -> Command {
+        let path = path.as_ref();
+        let mut command = Command::new(&self.program);
+        command.args(&self.args);
+        match self.flavor() {
+            Flavor::Helix => {
+                command.arg(format!("{}:{line}:{column}", path.display()));
+            }
+            Flavor::Vi => {
+                command.arg(format!("+{line}")).arg(path);
+            }
+            Flavor::Vim => {
+                command
+                    .arg(format!("+call cursor({line}, {column})"))
+                    .arg(path);
+            }
+            Flavor::Emacs => {
+                command.arg(format!("+{line}:{column}")).arg(path);
+            }
+            Flavor::VsCode => {
+                command
+                    .arg("--goto")
+                    .arg(format!("{}:{line}:{column}", path.display()));
+            }
+            Flavor::Other => {
+                command.arg(path);
+            }
+        }
+        command
+    }
+
+    /// Command opening the file of a config diagnostic at its position.
+    pub fn open_location(&self, location: &Location) -> Command {
+        self.open_at(&location.path, location.line, location.column)
+    }
+}
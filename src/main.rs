@@ -1,21 +1,92 @@
 #![allow(dead_code)]
-use anyhow::Result;
+use anyhow::{bail, Context as _, Result};
+use util::CheckExitStatus;
 
+mod cli;
 mod command_line;
 mod config;
+mod config_watch;
 mod data;
 mod direnv;
+mod editor;
 mod multi_term;
+mod trust;
 mod ui;
 mod util;
+mod window_watch;
 
 fn main() -> Result<()> {
+    let args = cli::Args::parse(std::env::args().skip(1))?;
+    let start_dir = std::env::current_dir()?;
+    if args.help {
+        print!("{}", cli::USAGE);
+        return Ok(());
+    }
+    if args.version {
+        println!("humsh {}", env!("CARGO_PKG_VERSION"));
+        return Ok(());
+    }
+    if let Some(config) = &args.config {
+        if !config.is_file() {
+            bail!("config {} not found", config.display());
+        }
+        // relative to where humsh was started, before `-C`
+        data::set_user_config_path(std::env::current_dir()?.join(config));
+    }
+    if let Some(dir) = &args.dir {
+        std::env::set_current_dir(dir).with_context(|| format!("cd {}", dir.display()))?;
+        std::env::set_var("PWD", std::env::current_dir()?);
+    }
+    if let Some(shell) = &args.init {
+        print!("{}", shell_widget(shell)?);
+        return Ok(());
+    }
+    if args.check_config {
+        return check_config(args.edit);
+    }
     let rt = tokio::runtime::Builder::new_multi_thread()
         .worker_threads(1)
         .enable_all()
         .build()?;
     let _guard = rt.enter();
-    let program = data::jj()?;
-    ui::Ui::new(program)?.run()?;
+    let program = data::program(args.program.as_deref())?;
+    match (&args.keys, args.emit) {
+        (Some(keys), _) => ui::Ui::new(program)?
+            .with_script(args.exec)
+            .run_script(keys)?,
+        (None, Some(fd)) => ui::Ui::new(program)?
+            .with_emit(fd, start_dir, args.shell.unwrap_or_default())?
+            .run()?,
+        (None, None) => ui::Ui::new(program)?.run()?,
+    }
+    Ok(())
+}
+
+fn shell_widget(shell: &str) -> Result<&'static str> {
+    Ok(match shell {
+        "zsh" => include_str!("../shell/humsh.zsh"),
+        "bash" => include_str!("../shell/humsh.bash"),
+        "fish" => include_str!("../shell/humsh.fish"),
+        _ => bail!("no widget for `{shell}`, only zsh, bash and fish are supported"),
+    })
+}
+
+fn check_config(edit: bool) -> Result<()> {
+    let diagnostics = data::config_diagnostics();
+    for diagnostic in &diagnostics {
+        eprintln!("{diagnostic}");
+    }
+    if let Some(location) = diagnostics.iter().find_map(|x| x.location.as_ref()) {
+        if edit {
+            let editor = editor::Editor::resolve(&data::ShellContext::new());
+            editor
+                .open_location(location)
+                .status()?
+                .check_exit_status()?;
+        }
+    }
+    if diagnostics.iter().any(|x| x.level == config::Level::Error) {
+        std::process::exit(1);
+    }
     Ok(())
 }
@@ -1,3 +1,4 @@
+use std::collections::HashSet;
 use std::{env, io, process::Command};
 
 use anyhow::Context as _;
@@ -10,13 +11,20 @@ enum TermDetect {
 
 pub struct MultiTerm {
     kind: TermDetect,
+    /// Ids of the windows opened by [`MultiTerm::run`].
+    spawned: HashSet<String>,
 }
 
-#[derive(serde::Deserialize, Clone, Debug)]
+/// Fields of [`TabHandle`] in tmux format.
+const WINDOW_FORMAT: &str =
+    "-F#{window_id},#{window_name},#{window_index},#{?window_active,true,false}";
+
+#[derive(serde::Deserialize, Clone, Debug, PartialEq, Eq)]
 pub struct TabHandle {
     window_id: String,
     name: String,
     number: u64,
+    active: bool,
 }
 
 impl TabHandle {
@@ -27,19 +35,44 @@ impl TabHandle {
     pub fn name(&self) -> &str {
         &self.name
     }
+
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+}
+
+fn parse_windows(output: &[u8]) -> anyhow::Result<Vec<TabHandle>> {
+    Ok(csv::ReaderBuilder::new()
+        .has_headers(false)
+        .from_reader(io::Cursor::new(output))
+        .deserialize()
+        .collect::<Result<_, _>>()?)
+}
+
+/// Like [`MultiTerm::list_windows`], without blocking.
+pub async fn poll_windows() -> anyhow::Result<Vec<TabHandle>> {
+    let output = tokio::process::Command::new("tmux")
+        .arg("list-windows")
+        .arg(WINDOW_FORMAT)
+        .stdin(std::process::Stdio::null())
+        .output()
+        .await?
+        .check_exit_status()?;
+    parse_windows(&output.stdout)
 }
 
 impl MultiTerm {
     pub fn run(&mut self, command: &mut Command) -> anyhow::Result<()> {
         let mut cmd = Command::new("tmux");
-        cmd.arg("new-window")
-            .arg("-F#{window_id},#{window_name},#{window_index}")
-            .arg("-P")
-            .envs(
-                command
-                    .get_envs()
-                    .filter_map(|(key, value)| Some((key, value?))),
-            );
+        cmd.arg("new-window").arg(WINDOW_FORMAT).arg("-P");
+        if let Some(dir) = command.get_current_dir() {
+            cmd.arg("-c").arg(dir);
+        }
+        cmd.envs(
+            command
+                .get_envs()
+                .filter_map(|(key, value)| Some((key, value?))),
+        );
 
         for (key, value) in command.get_envs() {
             if let Some(val) = value {
@@ -60,25 +93,24 @@ impl MultiTerm {
             .check_exit_status()
             .context("running tmux new window")?;
 
-        Ok(csv::ReaderBuilder::new()
-            .has_headers(false)
-            .from_reader(io::Cursor::new(output.stdout))
-            .deserialize()
-            .next()
-            .context("invalid tmux output")??)
+        let window = parse_windows(&output.stdout)?
+            .pop()
+            .context("invalid tmux output")?;
+        self.spawned.insert(window.window_id);
+        Ok(())
     }
 
     pub fn list_windows(&self) -> anyhow::Result<Vec<TabHandle>> {
         let output = Command::new("tmux")
             .arg("list-windows")
-            .arg("-F#{window_id},#{window_name},#{window_index}")
+            .arg(WINDOW_FORMAT)
             .output()?;
+        parse_windows(&output.stdout)
+    }
 
-        Ok(csv::ReaderBuilder::new()
-            .has_headers(false)
-            .from_reader(io::Cursor::new(output.stdout))
-            .deserialize()
-            .collect::<Result<_, _>>()?)
+    /// Whether humsh opened the window.
+    pub fn spawned(&self, handle: &TabHandle) -> bool {
+        self.spawned.contains(&handle.window_id)
     }
 
     pub fn focus(&self, handle: &TabHandle) -> anyhow::Result<()> {
@@ -95,6 +127,7 @@ pub fn detect() -> Option<MultiTerm> {
     if env::var("TMUX").is_ok() {
         return Some(MultiTerm {
             kind: TermDetect::Tmux,
+            spawned: HashSet::new(),
         });
     }
     None
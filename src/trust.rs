@@ -0,0 +1,110 @@
+//! Project configs run commands on a single keypress, so like `direnv allow`
+//! each one has to be trusted first. Trust is keyed by the path of the config
+//! and a hash of it, the files it includes and the program definitions next
+//! to it, so any change has to be trusted again.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context as _;
+use sha2::{Digest, Sha256};
+
+pub fn is_trusted(path: &Path, included: &[PathBuf]) -> bool {
+    let (Some(store), Ok(entry)) = (store_path(), entry(path, included)) else {
+        return false;
+    };
+    fs::read_to_string(store).is_ok_and(|x| x.lines().any(|line| line == entry))
+}
+
+/// Trusts the current contents of the config at `path`, replacing any earlier
+/// trust for it.
+pub fn trust(path: &Path, included: &[PathBuf]) -> anyhow::Result<()> {
+    let store = store_path().context("data dir not found")?;
+    let entry = entry(path, included)?;
+    let (_, key) = entry.split_once(' ').context("invalid trust entry")?;
+    let existing = fs::read_to_string(&store).unwrap_or_default();
+    let mut lines = existing
+        .lines()
+        .filter(|line| line.split_once(' ').map(|x| x.1) != Some(key))
+        .collect::<Vec<_>>();
+    lines.push(&entry);
+    if let Some(dir) = store.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(&store, lines.join("\n") + "\n")
+        .with_context(|| format!("writing {}", store.display()))
+}
+
+/// `<sha256> <canonical path>`
+fn entry(path: &Path, included: &[PathBuf]) -> anyhow::Result<String> {
+    // the config may not exist when a project only has program definitions
+    let dir = path.parent().context("config path has no parent")?;
+    let dir = fs::canonicalize(if dir.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        dir
+    })?;
+    let key = dir.join(path.file_name().context("config path has no file name")?);
+    let mut hasher = Sha256::new();
+    for file in [path]
+        .into_iter()
+        .chain(included.iter().map(PathBuf::as_path))
+    {
+        // missing includes still change the hash once they are created
+        match fs::read(file) {
+            Ok(text) => {
+                hasher.update((text.len() as u64).to_le_bytes());
+                hasher.update(text);
+            }
+            Err(_) => hasher.update(u64::MAX.to_le_bytes()),
+        }
+    }
+    let hash = hasher
+        .finalize()
+        .iter()
+        .map(|x| format!("{x:02x}"))
+        .collect::<String>();
+    Ok(format!("{hash} {}", key.display()))
+}
+
+fn store_path() -> Option<PathBuf> {
+    Some(dirs::data_dir()?.join("humsh/trusted"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entry_changes_with_included_and_program_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = dir.path().join("config.toml");
+        let include = dir.path().join("common.toml");
+        let program = dir.path().join("programs/cargo.toml");
+        fs::create_dir(dir.path().join("programs")).unwrap();
+        fs::write(&config, "include = [\"common.toml\"]\n").unwrap();
+        fs::write(&include, "").unwrap();
+        fs::write(&program, "").unwrap();
+        let files = [include.clone(), program.clone()];
+
+        let mut entries = vec![entry(&config, &files).unwrap()];
+        fs::write(&include, "[[commands]]\n").unwrap();
+        entries.push(entry(&config, &files).unwrap());
+        fs::write(&program, "[[commands]]\n").unwrap();
+        entries.push(entry(&config, &files).unwrap());
+        fs::remove_file(&program).unwrap();
+        entries.push(entry(&config, &files).unwrap());
+        // moving text between files changes the hash too
+        fs::write(&config, "include = [\"common.toml\"]\n[[commands]]\n").unwrap();
+        fs::write(&include, "").unwrap();
+        entries.push(entry(&config, &files).unwrap());
+
+        let key = format!(" {}", fs::canonicalize(&config).unwrap().display());
+        assert!(entries.iter().all(|x| x.ends_with(&key)), "{entries:?}");
+        let distinct = entries.iter().collect::<std::collections::HashSet<_>>();
+        assert_eq!(distinct.len(), entries.len(), "{entries:?}");
+        assert_eq!(entry(&config, &files).unwrap(), entries[4]);
+    }
+}
@@ -1,33 +1,52 @@
 use std::collections::BTreeMap;
 use std::ffi::OsStr;
-use std::io::{StdoutLock, Write};
+use std::io::Write;
 use std::path::PathBuf;
 use std::sync::Arc;
 
-use anyhow::{bail, Context as _};
+use anyhow::Context as _;
 use crossterm::{cursor, execute, queue, style::*, terminal};
 use tokio::runtime;
-use tokio_stream::StreamExt;
+use tokio_stream::{Stream, StreamExt};
 
 use crate::command_line::CommandLine;
-use crate::data::{Button, ButtonHandler, ButtonValue, Group, Page, Program};
+use crate::config_watch::ConfigWatcher;
+use crate::data::{self, Button, ButtonHandler, ButtonValue, Group, Page, Program, ShellContext};
 use crate::direnv::Direnv;
 use crate::multi_term::{self, MultiTerm, TabHandle};
-pub use context::{BgTaskId, Context, ExternalContext};
+use crate::window_watch::WindowWatcher;
+pub use backend::{Backend, Terminal};
+pub use context::{BgTaskId, Context, ExternalContext, FailedTask};
+use emit::Emit;
+pub use emit::Shell;
+use error_panel::{run_foreground, ErrorPanel};
 use input::KeyHandler;
-pub use style::Style;
+use layout::Line;
+pub use script::display_command;
+use script::Script;
+pub use style::{Style, Theme};
 
+pub mod backend;
 mod context;
+mod emit;
+mod error_panel;
 mod input;
+mod layout;
+mod script;
 mod style;
 
-pub type Stdout<'a, 'b> = &'a mut StdoutLock<'b>;
+pub type Stdout<'a, 'b> = &'a mut &'b mut dyn Backend;
 
 #[derive(Debug)]
 pub enum Event {
     Term(crossterm::event::Event),
     Task(BgTaskId, String),
     RemoveStatus(BgTaskId),
+    TaskFailed(FailedTask),
+    /// The config changed on disk.
+    Reload,
+    /// The windows of the multiplexer changed.
+    Windows(Vec<TabHandle>),
 }
 
 pub struct Ui {
@@ -35,18 +54,31 @@ pub struct Ui {
     program: Program,
     key_handler: KeyHandler,
     direnv: Direnv,
+    config_watcher: Option<ConfigWatcher>,
     showing_cmd: bool,
     multi_term: Option<MultiTerm>,
+    window_watcher: Option<WindowWatcher>,
+    /// The last windows sent by the watcher.
+    windows: Vec<TabHandle>,
     event_tx: flume::Sender<Event>,
     event_rx: flume::Receiver<Event>,
     background_tasks: BTreeMap<BgTaskId, String>,
+    failed_tasks: Vec<FailedTask>,
     style: Style,
+    /// Set by `capture_stderr` in the user config.
+    capture_stderr: bool,
     showing_pages: bool,
+    script: Option<Script>,
+    emit: Option<Emit>,
+    error_panel: Option<ErrorPanel>,
 }
 
 impl Ui {
     pub fn new(program: Program) -> anyhow::Result<Self> {
         let (event_tx, event_rx) = flume::bounded(10);
+        let multi_term = multi_term::detect();
+        let shell_context = ShellContext::new();
+        let user_config = shell_context.user_config();
         Ok(Self {
             showing_pages: program.start.show_by_default,
             program: program.clone(),
@@ -56,12 +88,29 @@ impl Ui {
                 ExternalContext::new(event_tx.clone()),
                 std::env::current_dir()?,
             )?,
+            config_watcher: program
+                .reload
+                .is_some()
+                .then(|| ConfigWatcher::new(ExternalContext::new(event_tx.clone()))),
             showing_cmd: false,
-            multi_term: multi_term::detect(),
+            window_watcher: multi_term
+                .is_some()
+                .then(|| WindowWatcher::new(ExternalContext::new(event_tx.clone()))),
+            windows: Vec::new(),
+            multi_term,
             event_tx,
             event_rx,
             background_tasks: BTreeMap::new(),
-            style: style::builtin(),
+            failed_tasks: Vec::new(),
+            style: Style::from_theme(
+                &user_config
+                    .and_then(|x| x.theme.clone())
+                    .unwrap_or_default(),
+            ),
+            capture_stderr: user_config.and_then(|x| x.capture_stderr).unwrap_or(false),
+            script: None,
+            emit: None,
+            error_panel: None,
         })
     }
 
@@ -89,25 +138,34 @@ impl Ui {
         self.event_tx.clone()
     }
 
-    pub fn run(mut self) -> anyhow::Result<()> {
-        let mut stdout = std::io::stdout().lock();
-        terminal::enable_raw_mode()?;
-        let mut event_stream = crossterm::event::EventStream::new();
+    pub fn run(self) -> anyhow::Result<()> {
+        self.run_with(
+            &mut Terminal::stdout(),
+            crossterm::event::EventStream::new(),
+        )
+    }
+
+    /// Runs the ui on `backend`, until `events` ends or the user exits.
+    pub fn run_with(
+        mut self,
+        mut stdout: &mut dyn Backend,
+        mut events: impl Stream<Item = std::io::Result<crossterm::event::Event>> + Unpin,
+    ) -> anyhow::Result<()> {
+        stdout.enable_raw_mode()?;
         loop {
-            terminal::enable_raw_mode()?;
+            stdout.enable_raw_mode()?;
             if !self.showing_cmd {
                 self.program.refresh_status().ok();
                 self.draw(&mut stdout)?;
             }
             let event: anyhow::Result<_> = runtime::Handle::current().block_on(async {
                 tokio::select! {
-                    Some(term) = event_stream.next() => {
-                        Ok(Some(Event::Term(term?)))
+                    term = events.next() => {
+                        Ok(term.transpose()?.map(Event::Term))
                     }
                     Ok(event) = self.event_rx.recv_async() => {
                         Ok(Some(event))
                     }
-                    else => Ok(None)
                 }
             });
             let Some(event) = event? else { break };
@@ -118,15 +176,8 @@ impl Ui {
                     ui: &mut self,
                     exit: &mut exit,
                 };
-                // FIXME proper error handling
-                if let Err(e) = callback.run(ctx) {
-                    self.leave_ui(&mut stdout)?;
-                    self.showing_cmd = true;
-                    execute!(
-                        stdout,
-                        PrintStyledContent(self.style.error.apply(format!("! {:#}", e))),
-                        NextLine,
-                    )?;
+                if let Err(e) = callback.clone().run(ctx) {
+                    self.show_error(e, callback);
                 }
                 if exit {
                     break;
@@ -134,6 +185,9 @@ impl Ui {
             }
         }
         self.leave_ui(&mut stdout)?;
+        if let Some(emit) = self.emit.take() {
+            emit.finish()?;
+        }
         Ok(())
     }
 
@@ -148,11 +202,7 @@ impl Ui {
         self.hint_running_command(&cli, stdout)?;
         let mut cmd = cmd.to_std();
         self.direnv.hook(&mut cmd)?;
-        let status = cmd.spawn()?.wait()?;
-        if !status.success() {
-            bail!("exit code {}", status.code().unwrap_or(-1));
-        }
-        Ok(())
+        run_foreground(&mut cmd, self.capture_stderr)
     }
 
     fn run_command_line(&mut self, stdout: Stdout) -> anyhow::Result<()> {
@@ -160,7 +210,23 @@ impl Ui {
         self.run_command_line_other(&cmd, stdout)
     }
 
+    fn print_error(&self, message: &str, stdout: Stdout) -> crossterm::Result<()> {
+        if self.is_scripted() {
+            eprintln!("! {message}");
+            return Ok(());
+        }
+        execute!(
+            stdout,
+            PrintStyledContent(self.style.error.apply(format!("! {message}"))),
+            NextLine,
+        )
+    }
+
     fn hint_running_command(&self, cmd: &str, stdout: Stdout) -> crossterm::Result<()> {
+        // nothing runs when emitting
+        if self.is_scripted() || self.emit.is_some() {
+            return Ok(());
+        }
         execute!(
             stdout,
             PrintStyledContent(self.style.command.apply(format!("> {cmd}\n")))
@@ -169,7 +235,10 @@ impl Ui {
     }
 
     fn enter_ui(&self, stdout: Stdout) -> crossterm::Result<()> {
-        terminal::enable_raw_mode()?;
+        if self.is_scripted() {
+            return Ok(());
+        }
+        stdout.enable_raw_mode()?;
         execute!(
             stdout,
             terminal::EnterAlternateScreen,
@@ -179,9 +248,12 @@ impl Ui {
     }
 
     fn leave_ui(&self, stdout: Stdout) -> crossterm::Result<()> {
+        if self.is_scripted() {
+            return Ok(());
+        }
         // always write at end of terminal
-        let (_, height) = terminal::size()?;
-        terminal::disable_raw_mode()?;
+        let (_, height) = stdout.size()?;
+        stdout.disable_raw_mode()?;
         execute!(
             stdout,
             terminal::LeaveAlternateScreen,
@@ -192,12 +264,18 @@ impl Ui {
     }
 
     fn hide_cmd(&mut self, stdout: Stdout) -> crossterm::Result<()> {
+        if self.is_scripted() {
+            return Ok(());
+        }
         execute!(stdout, terminal::EnterAlternateScreen)?;
         self.showing_cmd = false;
         Ok(())
     }
 
     fn show_cmd(&mut self, stdout: Stdout) -> crossterm::Result<()> {
+        if self.is_scripted() {
+            return Ok(());
+        }
         execute!(stdout, terminal::LeaveAlternateScreen)?;
         self.showing_cmd = true;
         Ok(())
@@ -218,6 +296,8 @@ impl Ui {
     ) -> anyhow::Result<Option<Arc<dyn ButtonHandler>>> {
         match event {
             Event::Term(crossterm::event::Event::Key(key)) => self.handle_key(key),
+            // the page is laid out again for the new size on the next draw
+            Event::Term(crossterm::event::Event::Resize(..)) => Ok(None),
             Event::Term(_) => Ok(None),
             Event::Task(id, text) => {
                 self.background_tasks.insert(id, text);
@@ -227,6 +307,24 @@ impl Ui {
                 self.background_tasks.remove(&id);
                 Ok(None)
             }
+            Event::TaskFailed(task) => {
+                self.failed_tasks.push(task);
+                Ok(None)
+            }
+            Event::Windows(windows) => {
+                self.windows = windows;
+                Ok(None)
+            }
+            #[allow(clippy::arc_with_non_send_sync)]
+            Event::Reload => {
+                let Some(reload) = self.program.reload.clone() else {
+                    return Ok(None);
+                };
+                Ok(Some(Arc::new(move |mut ctx: Context| {
+                    ctx.replace_root_page(reload()?);
+                    Ok(())
+                })))
+            }
         }
     }
 
@@ -234,37 +332,59 @@ impl Ui {
         &mut self,
         key: crossterm::event::KeyEvent,
     ) -> anyhow::Result<Option<Arc<dyn ButtonHandler>>> {
-        let page = &self.stack.last().expect("stack must not be empty").1;
-        if let Some(mux) = &mut self.multi_term {
+        if self.error_panel.is_some() {
+            return self.handle_error_key(key);
+        }
+        if key.code == crossterm::event::KeyCode::Char('p')
+            && key
+                .modifiers
+                .contains(crossterm::event::KeyModifiers::CONTROL)
+        {
+            return Ok(Some(Arc::new(data::palette)));
+        }
+        if key.code == crossterm::event::KeyCode::Char('!') && !self.failed_tasks.is_empty() {
+            return Ok(Some(Arc::new(|mut ctx: Context| ctx.show_failed_tasks())));
+        }
+        // digits typed after a prefix, like `T1`, belong to the binding
+        let prefix_pending = !self.key_handler.prefix().is_empty();
+        if let Some(mux) = self.multi_term.as_mut().filter(|_| !prefix_pending) {
             if let crossterm::event::KeyCode::Char(c) = key.code {
                 if let Some(d) = c.to_digit(10) {
-                    if let Some(handle) = mux
-                        .list_windows()?
-                        .into_iter()
-                        .find(|w| w.number() == d as u64)
-                    {
-                        mux.focus(&handle)?;
+                    if let Some(handle) = self.windows.iter().find(|w| w.number() == d as u64) {
+                        mux.focus(handle)?;
                         return Ok(None);
                     }
                 }
             }
         }
+        self.handle_page_key(key)
+    }
+
+    /// Looks `key` up in the buttons of the current page.
+    fn handle_page_key(
+        &mut self,
+        key: crossterm::event::KeyEvent,
+    ) -> anyhow::Result<Option<Arc<dyn ButtonHandler>>> {
+        let page = &self.stack.last().expect("stack must not be empty").1;
         self.key_handler.handle_key(
             key,
             page.groups
                 .iter()
                 .flat_map(|x| &x.buttons)
+                .filter(|b| !b.disabled)
                 .map(|b| (&b.key, &b.handler)),
         )
     }
 
+    /// Only the home page is rebuilt on reload, other programs like `humsh jj`
+    /// start on their own page.
+    fn on_home_page(&self) -> bool {
+        self.stack.len() == 1 && self.program.reload.is_some()
+    }
+
     pub fn draw(&self, stdout: Stdout) -> anyhow::Result<()> {
-        // let tabs = match &self.multi_term {
-        //     Some(mux) if self.stack.len() == 1 => Some(mux.list_windows()?),
-        //     _ => None,
-        // };
         self.enter_ui(stdout)?;
-        let (_, height) = terminal::size()?;
+        let (_, height) = stdout.size()?;
         // hack: to make terminal keep scrolling
         queue!(
             stdout,
@@ -272,16 +392,29 @@ impl Ui {
             terminal::Clear(terminal::ClearType::All)
         )?;
         self.draw_status(stdout)?;
-        if self.showing_pages {
+        if let Some(panel) = &self.error_panel {
+            self.draw_error_panel(panel, stdout)?;
+        } else if self.showing_pages || !self.key_handler.prefix().is_empty() {
             self.draw_page(self.currrent_page(), stdout)?;
         }
+        if let Some(keys) = self.key_handler.unbound() {
+            queue!(
+                stdout,
+                PrintStyledContent(self.style.error.apply(format!("no binding for `{keys}`"))),
+                NextLine
+            )?;
+        }
 
-        // if let Some(tabs) = tabs {
-        //     self.draw_tabs(&tabs, stdout)?;
-        // }
+        match &self.multi_term {
+            Some(mux) if self.on_home_page() && !self.windows.is_empty() => {
+                self.draw_tabs(mux, stdout)?;
+            }
+            _ => {}
+        }
 
         self.draw_prompt(stdout)?;
         stdout.flush()?;
+        stdout.end_frame();
         Ok(())
     }
 
@@ -298,7 +431,7 @@ impl Ui {
         self.draw_bg_status(stdout)?;
         queue!(
             stdout,
-            PrintStyledContent(self.style.prompt_char),
+            PrintStyledContent(self.style.prompt.apply(self.style.prompt_char.as_str())),
             Print(&cmd),
             Print(if cmd.is_empty() { "" } else { " " }),
             Print(self.key_handler.prefix()),
@@ -307,24 +440,37 @@ impl Ui {
     }
 
     fn draw_status(&self, stdout: Stdout) -> Result<(), anyhow::Error> {
-        Ok(if let Some(status) = self.program.status() {
-            execute!(stdout, Print(status.replace('\n', "\r\n")), NextLine)?;
-        })
+        if let Some(status) = self.program.status() {
+            queue!(stdout, Print(status.replace('\n', "\r\n")), NextLine)?;
+        }
+        Ok(())
     }
 
-    fn draw_tabs(&self, tabs: &[TabHandle], stdout: Stdout) -> crossterm::Result<()> {
+    /// The active window is marked with `*` and the ones humsh opened with `+`.
+    fn draw_tabs(&self, mux: &MultiTerm, stdout: Stdout) -> crossterm::Result<()> {
         queue!(
             stdout,
             Print(self.style.heading.apply("Tabs")),
             NextLine,
             Print(" ")
         )?;
-        for handle in tabs {
+        for handle in &self.windows {
+            let mut name = handle.name().to_owned();
+            if handle.is_active() {
+                name.push('*');
+            }
+            if mux.spawned(handle) {
+                name.push('+');
+            }
             queue!(
                 stdout,
-                Print(handle.number()),
+                PrintStyledContent(self.style.button.apply(handle.number())),
                 Print(" "),
-                Print(handle.name()),
+                PrintStyledContent(if handle.is_active() {
+                    self.style.flag_on.apply(name)
+                } else {
+                    self.style.normal.apply(name)
+                }),
                 Print("  ")
             )?;
         }
@@ -333,6 +479,16 @@ impl Ui {
     }
 
     fn draw_bg_status(&self, stdout: Stdout) -> crossterm::Result<()> {
+        if !self.failed_tasks.is_empty() {
+            queue!(
+                stdout,
+                PrintStyledContent(
+                    self.style
+                        .error
+                        .apply(format!(" [{} failed, ! to view]", self.failed_tasks.len()))
+                )
+            )?;
+        }
         if self.background_tasks.is_empty() {
             return Ok(());
         }
@@ -359,83 +515,97 @@ impl Ui {
     }
 
     fn draw_page(&self, page: &Page, stdout: Stdout) -> Result<(), std::io::Error> {
-        for group in &page.groups {
-            self.draw_group(group, stdout)?;
+        for line in &page.banner {
+            queue!(
+                stdout,
+                PrintStyledContent(self.style.error.apply(line.as_str())),
+                NextLine
+            )?;
+        }
+        if !page.banner.is_empty() {
             queue!(stdout, NextLine)?;
         }
-        Ok(())
-    }
-
-    fn draw_group(&self, group: &Group, stdout: Stdout) -> crossterm::Result<()> {
-        queue!(
-            stdout,
-            PrintStyledContent(self.style.heading.apply(&*group.description)),
-            NextLine,
-        )?;
-        for button in &group.buttons {
-            if button.hidden {
+        // while a sequence is pending only what can still follow it is shown
+        let prefix = self.key_handler.prefix();
+        let mut blocks = Vec::new();
+        for group in &page.groups {
+            let buttons = group
+                .buttons
+                .iter()
+                .filter(|x| !x.hidden && x.key.0.starts_with(prefix))
+                .collect::<Vec<_>>();
+            if buttons.is_empty() && !prefix.is_empty() {
                 continue;
             }
-            self.draw_button(button, stdout)?;
-            queue!(stdout, NextLine)?;
+            blocks.push(self.group_lines(group, &buttons));
         }
+        let (width, _) = stdout.size()?;
+        layout::draw_columns(blocks, width, stdout)?;
+        queue!(stdout, NextLine)?;
         Ok(())
     }
 
-    fn draw_button(&self, button: &Button, stdout: Stdout) -> crossterm::Result<()> {
-        queue!(
-            stdout,
-            Print(" "),
-            PrintStyledContent(self.style.button.apply(&*button.key.0)),
-            Print(" "),
-            Print(&button.description),
-        )?;
+    fn group_lines(&self, group: &Group, buttons: &[&Button]) -> Vec<Line> {
+        let mut lines = vec![vec![self.style.heading.apply(group.description.clone())]];
+        lines.extend(buttons.iter().map(|x| self.button_line(x)));
+        lines
+    }
+
+    fn button_line(&self, button: &Button) -> Line {
+        let plain = |text: &str| ContentStyle::new().apply(text.to_owned());
+        if button.disabled {
+            return vec![
+                plain(" "),
+                self.style.flag_off.apply(button.key.0.clone()),
+                plain(" "),
+                self.style.flag_off.apply(button.description.clone()),
+            ];
+        }
+        let mut line = vec![
+            plain(" "),
+            self.style.button.apply(button.key.0.clone()),
+            plain(" "),
+            plain(&button.description),
+        ];
         if let Some(value) = button.handler.value(self.command_line()) {
-            match value {
+            let value = match value {
                 ButtonValue::String { name, value } => {
-                    queue!(
-                        stdout,
-                        Print(" ("),
-                        PrintStyledContent(if let Some(value) = value {
-                            self.style.flag_on.apply(format!("{name}={value}"))
-                        } else {
-                            self.style.flag_off.apply(name.to_string())
-                        }),
-                        Print(")")
-                    )?;
+                    if let Some(value) = value {
+                        self.style.flag_on.apply(format!("{name}={value}"))
+                    } else {
+                        self.style.flag_off.apply(name.to_string())
+                    }
                 }
                 ButtonValue::Bool { name, value } => {
-                    queue!(
-                        stdout,
-                        Print(" ("),
-                        PrintStyledContent(if value {
-                            self.style.flag_on.apply(name)
-                        } else {
-                            self.style.flag_off.apply(name)
-                        }),
-                        Print(")")
-                    )?;
+                    if value {
+                        self.style.flag_on.apply(name.to_string())
+                    } else {
+                        self.style.flag_off.apply(name.to_string())
+                    }
                 }
-            }
+            };
+            line.extend([plain(" ("), value, plain(")")]);
         }
-        Ok(())
+        line
     }
 
     pub fn read_input(&self, stdout: Stdout, prompt: &str) -> anyhow::Result<String> {
+        if self.is_scripted() {
+            eprint!("{prompt}: ");
+            let mut buf = String::new();
+            std::io::stdin().read_line(&mut buf)?;
+            return Ok(buf.trim_end_matches('\n').to_owned());
+        }
         execute!(
             stdout,
             cursor::MoveToColumn(0),
             terminal::Clear(terminal::ClearType::CurrentLine),
             Print(format!("{prompt}: "))
         )?;
-        terminal::disable_raw_mode()?;
-        let mut buf = String::new();
-        std::io::stdin().read_line(&mut buf)?;
-        if buf.as_bytes().last() == Some(&b'\n') {
-            buf.truncate(buf.len() - 1);
-        }
-        terminal::enable_raw_mode()?;
-        Ok(buf)
+        stdout.disable_raw_mode()?;
+        let line = stdout.read_line()?;
+        stdout.enable_raw_mode()?;
+        Ok(line)
     }
 }
 
@@ -452,3 +622,77 @@ impl crossterm::Command for NextLine {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crossterm::event::{Event as TermEvent, KeyCode, KeyEvent};
+
+    use super::backend::{Frame, VirtualScreen};
+    use super::*;
+
+    /// Runs the jj program on a virtual screen, typing `keys`.
+    fn run_jj(keys: &str) -> VirtualScreen {
+        // keep the user's key remaps out of the page
+        data::set_user_config_path(PathBuf::from("/nonexistent/humsh.toml"));
+        let rt = runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()
+            .unwrap();
+        let _guard = rt.enter();
+        let events = keys
+            .chars()
+            .map(|c| Ok(TermEvent::Key(KeyEvent::from(KeyCode::Char(c)))))
+            .collect::<Vec<_>>();
+        let mut screen = VirtualScreen::new(100, 40);
+        Ui::new(data::jj().unwrap())
+            .unwrap()
+            .run_with(&mut screen, tokio_stream::iter(events))
+            .unwrap();
+        screen
+    }
+
+    /// The last row drawn, where the command line is.
+    fn command_line(frame: &Frame) -> String {
+        frame.text().lines().last().unwrap_or_default().to_owned()
+    }
+
+    /// The style `text` is drawn in.
+    fn style_of(frame: &Frame, text: &str) -> String {
+        let lines = frame.text();
+        let (row, line) = lines
+            .lines()
+            .enumerate()
+            .find(|(_, x)| x.contains(text))
+            .unwrap();
+        let column = line[..line.find(text).unwrap()].chars().count();
+        frame
+            .style_at(column as u16, row as u16)
+            .unwrap()
+            .to_owned()
+    }
+
+    #[test]
+    fn draws_the_page_and_flags() {
+        let screen = run_jj("pd");
+        let frames = &screen.frames;
+        assert!(command_line(&frames[0]).ends_with("λ jj"));
+        // background tasks may draw more frames, but not between keys
+        let push = frames
+            .iter()
+            .find(|x| x.text().contains("d Deleted (--deleted)"))
+            .unwrap();
+        assert!(push.text().contains("p Push"), "{}", push.text());
+        assert!(command_line(push).ends_with("λ jj git push"));
+        let last = frames.last().unwrap();
+        assert!(command_line(last).ends_with("λ jj git push --deleted"));
+        assert_ne!(style_of(push, "--deleted"), style_of(last, "--deleted"));
+    }
+
+    #[test]
+    fn toggles_flags_off() {
+        let screen = run_jj("pdd");
+        let last = screen.frames.last().unwrap();
+        assert!(command_line(last).ends_with("λ jj git push"));
+    }
+}
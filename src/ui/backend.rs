@@ -0,0 +1,399 @@
+use std::collections::{BTreeSet, VecDeque};
+use std::fmt;
+use std::io::{self, StdoutLock, Write};
+
+use crossterm::terminal;
+
+/// Where the ui is drawn. Drawing goes through `Write` with crossterm
+/// commands, the rest is what crossterm would otherwise do on stdout.
+pub trait Backend: Write {
+    /// Columns and rows.
+    fn size(&self) -> io::Result<(u16, u16)>;
+    fn enable_raw_mode(&mut self) -> io::Result<()>;
+    fn disable_raw_mode(&mut self) -> io::Result<()>;
+    /// Reads a line typed at a prompt, without the newline.
+    fn read_line(&mut self) -> io::Result<String>;
+    /// Called after each complete draw.
+    fn end_frame(&mut self) {}
+}
+
+/// The real terminal.
+pub struct Terminal {
+    stdout: StdoutLock<'static>,
+}
+
+impl Terminal {
+    pub fn stdout() -> Self {
+        Terminal {
+            stdout: io::stdout().lock(),
+        }
+    }
+}
+
+impl Write for Terminal {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.stdout.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stdout.flush()
+    }
+}
+
+impl Backend for Terminal {
+    fn size(&self) -> io::Result<(u16, u16)> {
+        terminal::size()
+    }
+
+    fn enable_raw_mode(&mut self) -> io::Result<()> {
+        terminal::enable_raw_mode()
+    }
+
+    fn disable_raw_mode(&mut self) -> io::Result<()> {
+        terminal::disable_raw_mode()
+    }
+
+    fn read_line(&mut self) -> io::Result<String> {
+        let mut buf = String::new();
+        io::stdin().read_line(&mut buf)?;
+        if buf.as_bytes().last() == Some(&b'\n') {
+            buf.truncate(buf.len() - 1);
+        }
+        Ok(buf)
+    }
+}
+
+/// One character on a [`VirtualScreen`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cell {
+    pub ch: char,
+    /// The SGR parameters it was printed with, e.g. `38;5;2`, empty when
+    /// unstyled.
+    pub style: String,
+}
+
+impl Default for Cell {
+    fn default() -> Self {
+        Cell {
+            ch: ' ',
+            style: String::new(),
+        }
+    }
+}
+
+/// What a [`VirtualScreen`] showed at the end of a draw.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    pub rows: Vec<Vec<Cell>>,
+}
+
+impl Frame {
+    /// The text of every row with trailing blanks removed, trailing empty
+    /// rows are dropped.
+    pub fn text(&self) -> String {
+        let mut lines = self
+            .rows
+            .iter()
+            .map(|row| {
+                let line = row.iter().map(|x| x.ch).collect::<String>();
+                line.trim_end().to_owned()
+            })
+            .collect::<Vec<_>>();
+        while lines.last().is_some_and(|x| x.is_empty()) {
+            lines.pop();
+        }
+        lines.join("\n")
+    }
+
+    pub fn style_at(&self, column: u16, row: u16) -> Option<&str> {
+        let cell = self.rows.get(row as usize)?.get(column as usize)?;
+        Some(&cell.style)
+    }
+}
+
+/// The current colors and attributes, set by `ESC [ ... m`.
+#[derive(Debug, Default)]
+struct Sgr {
+    attributes: BTreeSet<u16>,
+    foreground: Option<String>,
+    background: Option<String>,
+}
+
+impl Sgr {
+    fn apply(&mut self, params: &str) {
+        let mut params = params.split(';').map(|x| x.parse::<u16>().unwrap_or(0));
+        while let Some(param) = params.next() {
+            // `38;5;n` and `38;2;r;g;b`
+            let mut extended = |prefix: u16| {
+                let mut color = vec![prefix];
+                let kind = params.next().unwrap_or(5);
+                color.push(kind);
+                color.extend(params.by_ref().take(if kind == 2 { 3 } else { 1 }));
+                color
+                    .iter()
+                    .map(u16::to_string)
+                    .collect::<Vec<_>>()
+                    .join(";")
+            };
+            match param {
+                0 => *self = Sgr::default(),
+                1..=9 => {
+                    self.attributes.insert(param);
+                }
+                22 => {
+                    self.attributes.remove(&1);
+                    self.attributes.remove(&2);
+                }
+                21..=29 => {
+                    self.attributes.remove(&(param - 20));
+                }
+                30..=37 | 90..=97 => self.foreground = Some(param.to_string()),
+                38 => self.foreground = Some(extended(38)),
+                39 => self.foreground = None,
+                40..=47 | 100..=107 => self.background = Some(param.to_string()),
+                48 => self.background = Some(extended(48)),
+                49 => self.background = None,
+                _ => {}
+            }
+        }
+    }
+}
+
+impl fmt::Display for Sgr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let parts = self
+            .attributes
+            .iter()
+            .map(u16::to_string)
+            .chain(self.foreground.clone())
+            .chain(self.background.clone())
+            .collect::<Vec<_>>();
+        f.write_str(&parts.join(";"))
+    }
+}
+
+/// An in-memory terminal that understands the escape sequences the ui
+/// writes, so frames can be inspected without a real terminal.
+#[derive(Debug)]
+pub struct VirtualScreen {
+    width: u16,
+    height: u16,
+    main: Vec<Vec<Cell>>,
+    alternate: Vec<Vec<Cell>>,
+    in_alternate: bool,
+    cursor: (u16, u16),
+    style: Sgr,
+    raw: bool,
+    /// Bytes of an incomplete escape sequence or character.
+    pending: Vec<u8>,
+    /// Lines returned by `read_line`.
+    pub input: VecDeque<String>,
+    /// The screen after every draw.
+    pub frames: Vec<Frame>,
+}
+
+impl VirtualScreen {
+    pub fn new(width: u16, height: u16) -> Self {
+        let blank = vec![vec![Cell::default(); width as usize]; height as usize];
+        VirtualScreen {
+            width,
+            height,
+            main: blank.clone(),
+            alternate: blank,
+            in_alternate: false,
+            cursor: (0, 0),
+            style: Sgr::default(),
+            raw: false,
+            pending: Vec::new(),
+            input: VecDeque::new(),
+            frames: Vec::new(),
+        }
+    }
+
+    pub fn is_raw(&self) -> bool {
+        self.raw
+    }
+
+    pub fn in_alternate_screen(&self) -> bool {
+        self.in_alternate
+    }
+
+    /// What is shown right now.
+    pub fn frame(&self) -> Frame {
+        Frame {
+            rows: self.rows().clone(),
+        }
+    }
+
+    fn rows(&self) -> &Vec<Vec<Cell>> {
+        if self.in_alternate {
+            &self.alternate
+        } else {
+            &self.main
+        }
+    }
+
+    fn rows_mut(&mut self) -> &mut Vec<Vec<Cell>> {
+        if self.in_alternate {
+            &mut self.alternate
+        } else {
+            &mut self.main
+        }
+    }
+
+    fn blank_row(&self) -> Vec<Cell> {
+        vec![Cell::default(); self.width as usize]
+    }
+
+    fn print(&mut self, ch: char) {
+        match ch {
+            '\r' => self.cursor.0 = 0,
+            '\n' => self.line_feed(),
+            _ => {
+                if self.cursor.0 >= self.width {
+                    self.cursor.0 = 0;
+                    self.line_feed();
+                }
+                let (column, row) = self.cursor;
+                let style = self.style.to_string();
+                self.rows_mut()[row as usize][column as usize] = Cell { ch, style };
+                self.cursor.0 += 1;
+            }
+        }
+    }
+
+    fn line_feed(&mut self) {
+        if self.cursor.1 + 1 < self.height {
+            self.cursor.1 += 1;
+        } else {
+            let blank = self.blank_row();
+            let rows = self.rows_mut();
+            rows.remove(0);
+            rows.push(blank);
+        }
+    }
+
+    /// Applies the sequence `ESC [ params final`.
+    fn csi(&mut self, params: &str, final_byte: char) {
+        let numbers = params
+            .trim_start_matches('?')
+            .split(';')
+            .map(|x| x.parse::<u16>().ok())
+            .collect::<Vec<_>>();
+        let number = |i: usize, default: u16| numbers.get(i).copied().flatten().unwrap_or(default);
+        match (params.starts_with('?'), final_byte) {
+            (false, 'H') => {
+                let row = number(0, 1).clamp(1, self.height) - 1;
+                let column = number(1, 1).clamp(1, self.width) - 1;
+                self.cursor = (column, row);
+            }
+            (false, 'G') => self.cursor.0 = number(0, 1).clamp(1, self.width) - 1,
+            (false, 'J') if number(0, 0) >= 2 => {
+                let blank = vec![self.blank_row(); self.height as usize];
+                *self.rows_mut() = blank;
+            }
+            (false, 'K') => {
+                let (column, row) = self.cursor;
+                let row = &mut self.rows_mut()[row as usize];
+                let end = row.len();
+                let range = match number(0, 0) {
+                    0 => column as usize..end,
+                    1 => 0..(column as usize + 1).min(end),
+                    _ => 0..end,
+                };
+                for cell in &mut row[range] {
+                    *cell = Cell::default();
+                }
+            }
+            (false, 'm') => self.style.apply(params),
+            (true, 'h' | 'l') if number(0, 0) == 1049 => {
+                self.in_alternate = final_byte == 'h';
+                if self.in_alternate {
+                    self.alternate = vec![self.blank_row(); self.height as usize];
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Parses as much of `pending` as is complete.
+    fn process(&mut self) {
+        let bytes = std::mem::take(&mut self.pending);
+        let mut i = 0;
+        while i < bytes.len() {
+            if bytes[i] == 0x1b {
+                // ESC [ params final
+                if bytes.get(i + 1) != Some(&b'[') {
+                    if i + 1 >= bytes.len() {
+                        break;
+                    }
+                    i += 2;
+                    continue;
+                }
+                let Some(end) = bytes[i + 2..]
+                    .iter()
+                    .position(|x| (0x40..=0x7e).contains(x))
+                else {
+                    break;
+                };
+                let params = String::from_utf8_lossy(&bytes[i + 2..i + 2 + end]).into_owned();
+                self.csi(&params, bytes[i + 2 + end] as char);
+                i += end + 3;
+                continue;
+            }
+            let len = match bytes[i] {
+                0x00..=0x7f => 1,
+                0xc0..=0xdf => 2,
+                0xe0..=0xef => 3,
+                _ => 4,
+            };
+            if i + len > bytes.len() {
+                break;
+            }
+            for ch in String::from_utf8_lossy(&bytes[i..i + len]).chars() {
+                self.print(ch);
+            }
+            i += len;
+        }
+        self.pending = bytes[i..].to_vec();
+    }
+}
+
+impl Write for VirtualScreen {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.pending.extend_from_slice(buf);
+        self.process();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Backend for VirtualScreen {
+    fn size(&self) -> io::Result<(u16, u16)> {
+        Ok((self.width, self.height))
+    }
+
+    fn enable_raw_mode(&mut self) -> io::Result<()> {
+        self.raw = true;
+        Ok(())
+    }
+
+    fn disable_raw_mode(&mut self) -> io::Result<()> {
+        self.raw = false;
+        Ok(())
+    }
+
+    fn read_line(&mut self) -> io::Result<String> {
+        self.input
+            .pop_front()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "no more input"))
+    }
+
+    fn end_frame(&mut self) {
+        let frame = self.frame();
+        self.frames.push(frame);
+    }
+}
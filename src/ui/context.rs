@@ -1,4 +1,5 @@
 use std::{
+    io::Write,
     path::Path,
     process,
     sync::atomic::{AtomicU64, Ordering},
@@ -6,9 +7,17 @@ use std::{
 
 use anyhow::Context as _;
 
-use crate::{command_line::CommandLine, data::Page, direnv::Direnv, util::CheckExitStatus};
+use crate::{
+    command_line::CommandLine,
+    data::{self, Page},
+    direnv::Direnv,
+    multi_term::TabHandle,
+    util::CheckExitStatus,
+};
 
-use super::{Event, Stdout, Ui};
+use super::input::KeyHandler;
+use super::script::parse_keys;
+use super::{display_command, run_foreground, Event, Stdout, Ui};
 
 pub struct Context<'a, 'b> {
     pub(super) stdout: Stdout<'a, 'b>,
@@ -16,7 +25,7 @@ pub struct Context<'a, 'b> {
     pub(super) exit: &'a mut bool,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ExternalContext {
     tx: flume::Sender<Event>,
 }
@@ -24,33 +33,50 @@ pub struct ExternalContext {
 #[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Clone, Copy, Hash)]
 pub struct BgTaskId(pub u64);
 
+/// A background command that failed, kept until the user looks at it.
+#[derive(Debug, Clone)]
+pub struct FailedTask {
+    pub name: String,
+    pub error: String,
+    pub output: String,
+}
+
 impl ExternalContext {
     pub(super) fn new(tx: flume::Sender<Event>) -> Self {
         Self { tx }
     }
 
+    /// Background tasks can outlive the ui, e.g. after `--keys`, so a closed
+    /// channel is not an error.
+    async fn send(&self, event: Event) {
+        let _ = self.tx.send_async(event).await;
+    }
+
     pub async fn begin_status(&self, message: impl Into<String>) -> BgTaskId {
         static STATUS_ID: AtomicU64 = AtomicU64::new(0);
-        let status_id = BgTaskId(STATUS_ID.fetch_add(0, Ordering::SeqCst));
-        self.tx
-            .send_async(Event::Task(status_id, message.into()))
-            .await
-            .expect("ui is not running");
+        let status_id = BgTaskId(STATUS_ID.fetch_add(1, Ordering::SeqCst));
+        self.send(Event::Task(status_id, message.into())).await;
         status_id
     }
 
     pub async fn update_status(&self, id: BgTaskId, message: impl Into<String>) {
-        self.tx
-            .send_async(Event::Task(id, message.into()))
-            .await
-            .expect("ui is not running")
+        self.send(Event::Task(id, message.into())).await
     }
 
     pub async fn remove_status(&self, id: BgTaskId) {
-        self.tx
-            .send_async(Event::RemoveStatus(id))
-            .await
-            .expect("ui is not running")
+        self.send(Event::RemoveStatus(id)).await
+    }
+
+    pub async fn report_failure(&self, task: FailedTask) {
+        self.send(Event::TaskFailed(task)).await
+    }
+
+    pub async fn request_reload(&self) {
+        self.send(Event::Reload).await
+    }
+
+    pub async fn update_windows(&self, windows: Vec<TabHandle>) {
+        self.send(Event::Windows(windows)).await
     }
 }
 
@@ -91,6 +117,40 @@ impl<'a, 'b> Context<'a, 'b> {
         *self.ui.currrent_page_mut() = page;
     }
 
+    /// Replaces the first page, keeping the pages pushed on top of it.
+    pub fn replace_root_page(&mut self, page: Page) {
+        self.ui
+            .stack
+            .first_mut()
+            .expect("stack must not be empty")
+            .1 = page;
+    }
+
+    /// The page the program started on.
+    pub fn root_page(&self) -> &Page {
+        &self.ui.stack.first().expect("stack must not be empty").1
+    }
+
+    /// Presses the buttons bound to `keys` on the pages they open, see
+    /// [`parse_keys`]. Window and palette keys are not handled.
+    pub fn press_keys(&mut self, keys: &str) -> anyhow::Result<()> {
+        self.ui.key_handler = KeyHandler::new();
+        for key in parse_keys(keys) {
+            let Some(callback) = self.ui.handle_page_key(key)? else {
+                continue;
+            };
+            callback.run(Context {
+                stdout: &mut *self.stdout,
+                ui: &mut *self.ui,
+                exit: &mut *self.exit,
+            })?;
+            if *self.exit {
+                break;
+            }
+        }
+        Ok(())
+    }
+
     /// Returns whether page was poped.
     pub fn pop_page(&mut self) -> bool {
         let value = if self.ui.stack.len() > 1 {
@@ -98,7 +158,7 @@ impl<'a, 'b> Context<'a, 'b> {
         } else {
             false
         };
-        let show = self.ui.stack.first().map_or(false, |x| x.1.show_by_default);
+        let show = self.ui.stack.first().is_some_and(|x| x.1.show_by_default);
         self.show_pages(show);
         value
     }
@@ -123,37 +183,79 @@ impl<'a, 'b> Context<'a, 'b> {
         self.ui.showing_pages = !self.ui.showing_pages;
         Ok(())
     }
-    pub fn show_pages(&mut self, show: bool) -> anyhow::Result<()> {
+    pub fn show_pages(&mut self, show: bool) {
         self.ui.showing_pages = show;
-        Ok(())
     }
 
     pub fn exit(&mut self) {
         *self.exit = true;
     }
 
+    /// Whether someone is at the terminal, not `--keys` or `--emit`.
+    pub fn is_interactive(&self) -> bool {
+        !self.ui.is_scripted() && self.ui.emit.is_none()
+    }
+
     pub fn change_dir(&mut self, path: impl AsRef<Path>) -> anyhow::Result<()> {
         std::env::set_current_dir(&path).context("cd failed")?;
         std::env::set_var("PWD", path.as_ref());
+        data::clear_predicate_cache();
         self.ui.direnv = Direnv::new(self.external_ctx(), std::env::current_dir()?)?;
         Ok(())
     }
 
     pub fn run_command_line_other(&mut self, cmd: &CommandLine) -> anyhow::Result<()> {
+        if !self.intercept_command_line(cmd) {
+            return Ok(());
+        }
         self.ui.run_command_line_other(cmd, self.stdout)
     }
 
     pub fn run_command_line(&mut self) -> anyhow::Result<()> {
-        self.ui.run_command_line(self.stdout)
+        let cmd = self.command_line().clone();
+        self.run_command_line_other(&cmd)
+    }
+
+    /// When emitting, the next command is handed to the parent shell as
+    /// `script` instead of its program and arguments. With
+    /// `continue_on_error` the commands after it still run when it fails.
+    pub fn emit_as(&mut self, script: String, continue_on_error: bool) {
+        if let Some(emit) = &mut self.ui.emit {
+            emit.next_as(script, continue_on_error);
+        }
+    }
+
+    /// Returns whether `command` should be run here. When emitting it is
+    /// handed to the parent shell instead and humsh exits.
+    fn intercept(&mut self, command: &process::Command) -> bool {
+        if let Some(emit) = &mut self.ui.emit {
+            emit.push_command(command);
+            self.exit();
+            return false;
+        }
+        self.ui.script_record(|| display_command(command))
+    }
+
+    /// Like [`Context::intercept`] for a command line run in the current
+    /// directory.
+    fn intercept_command_line(&mut self, cmd: &CommandLine) -> bool {
+        if let Some(emit) = &mut self.ui.emit {
+            emit.push_command(&cmd.to_std());
+            self.exit();
+            return false;
+        }
+        self.ui.script_record(|| cmd.to_shell())
     }
 
     pub fn run_command_new_term(&mut self, command: &mut process::Command) -> anyhow::Result<()> {
         self.ui.direnv.hook(command)?;
+        if !self.intercept(command) {
+            return Ok(());
+        }
         if let Some(mux) = self.ui.multi_term() {
             mux.run(command)
         } else {
-            command.spawn()?.wait()?.check_exit_status()?;
-            Ok(())
+            run_foreground(command, false)
         }
     }
 
@@ -166,8 +268,71 @@ impl<'a, 'b> Context<'a, 'b> {
         &mut self,
         command: &mut process::Command,
     ) -> anyhow::Result<()> {
+        self.ui.direnv.hook(command)?;
+        self.leave_ui()?;
+        if !self.intercept(command) {
+            return Ok(());
+        }
+        run_foreground(command, self.ui.capture_stderr)
+    }
+
+    /// Runs `command` on the runtime, showing `name` in the status area while
+    /// it runs. The output is kept if it fails, see [`Context::show_failed_tasks`].
+    pub fn run_command_in_background(
+        &mut self,
+        name: &str,
+        mut command: process::Command,
+    ) -> anyhow::Result<()> {
+        self.ui.direnv.hook(&mut command)?;
+        if !self.intercept(&command) {
+            return Ok(());
+        }
+        if self.ui.is_scripted() {
+            self.run_script_background(name, command);
+            return Ok(());
+        }
+        let ctx = self.external_ctx();
+        let name = name.to_owned();
+        let mut command = tokio::process::Command::from(command);
+        command.stdin(process::Stdio::null());
+        tokio::spawn(async move {
+            let id = ctx.begin_status(name.clone()).await;
+            let result = command.output().await;
+            ctx.remove_status(id).await;
+            let (error, output) = match result {
+                Ok(output) => match output.status.check_exit_status() {
+                    Ok(_) => return,
+                    Err(e) => {
+                        let mut text = String::from_utf8_lossy(&output.stdout).into_owned();
+                        text.push_str(&String::from_utf8_lossy(&output.stderr));
+                        (e, text)
+                    }
+                },
+                Err(e) => (e.into(), String::new()),
+            };
+            ctx.report_failure(FailedTask {
+                name,
+                error: format!("{error:#}"),
+                output,
+            })
+            .await;
+        });
+        Ok(())
+    }
+
+    pub fn print_error(&mut self, message: &str) -> anyhow::Result<()> {
+        Ok(self.ui.print_error(message, self.stdout)?)
+    }
+
+    /// Prints the output of failed background commands on the main screen.
+    pub fn show_failed_tasks(&mut self) -> anyhow::Result<()> {
         self.leave_ui()?;
-        self.run_command(command)?.wait()?.check_exit_status()?;
+        self.show_cmd()?;
+        for task in std::mem::take(&mut self.ui.failed_tasks) {
+            self.ui
+                .print_error(&format!("{}: {}", task.name, task.error), self.stdout)?;
+            self.stdout.write_all(task.output.as_bytes())?;
+        }
         Ok(())
     }
 
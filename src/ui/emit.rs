@@ -0,0 +1,312 @@
+use std::borrow::Cow;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::str::FromStr;
+
+use anyhow::{bail, Context as _};
+
+use crate::util::{fish_quote, is_plain_word, shell_quote};
+
+use super::Ui;
+
+/// Set by `--emit`: instead of running a command humsh exits and writes it
+/// to a file descriptor, so a shell widget can put it on the command line.
+#[derive(Debug)]
+pub(super) struct Emit {
+    out: File,
+    /// The directory humsh started in, the parent shell is still there.
+    start_dir: PathBuf,
+    shell: Shell,
+    commands: Vec<Emitted>,
+    /// How the next command is written, see [`Emit::next_as`].
+    next: Option<(String, bool)>,
+}
+
+/// The shell the widget that started humsh runs in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Shell {
+    /// zsh, bash and other POSIX shells.
+    #[default]
+    Posix,
+    /// fish has no subshells or `{ }` groups.
+    Fish,
+}
+
+impl FromStr for Shell {
+    type Err = anyhow::Error;
+
+    fn from_str(name: &str) -> anyhow::Result<Shell> {
+        Ok(match name {
+            "sh" | "bash" | "zsh" => Shell::Posix,
+            "fish" => Shell::Fish,
+            _ => bail!("unknown shell `{name}`, expected zsh, bash or fish"),
+        })
+    }
+}
+
+#[derive(Debug)]
+struct Emitted {
+    dir: Option<PathBuf>,
+    env: Vec<(String, String)>,
+    line: Line,
+    continue_on_error: bool,
+}
+
+#[derive(Debug)]
+enum Line {
+    /// A program and its arguments.
+    Argv(Vec<String>),
+    /// A script for a POSIX shell, from the config.
+    Script(String),
+}
+
+impl Emitted {
+    /// The command for the parent shell. The directory and environment are
+    /// set in a subshell, or `bash -c` in fish, so they do not stick.
+    fn to_shell(&self, start_dir: &Path, shell: Shell) -> String {
+        let mut setup = Vec::new();
+        if let Some(dir) = self.dir.as_ref().filter(|x| *x != start_dir) {
+            setup.push(format!("cd {}", shell_quote(&dir.to_string_lossy())));
+        }
+        for (key, value) in &self.env {
+            setup.push(format!("export {key}={}", shell_quote(value)));
+        }
+        let line = match &self.line {
+            Line::Argv(args) => join(args, shell_quote),
+            Line::Script(script) => script.clone(),
+        };
+        if shell == Shell::Fish {
+            return match &self.line {
+                Line::Argv(args) if setup.is_empty() => join(args, fish_quote),
+                Line::Script(script) if setup.is_empty() && is_plain(script) => line,
+                // fish has no subshells, and scripts are written for bash
+                _ => {
+                    setup.push(line);
+                    format!("bash -c {}", fish_quote(&setup.join(" && ")))
+                }
+            };
+        }
+        // scripts with several commands are kept together when joined
+        let line = if line.contains('\n') {
+            format!("{line}\n")
+        } else {
+            line
+        };
+        if !setup.is_empty() {
+            format!("({} && {line})", setup.join(" && "))
+        } else if line.contains([';', '\n']) {
+            format!("({line})")
+        } else {
+            line
+        }
+    }
+}
+
+fn join(args: &[String], quote: fn(&str) -> Cow<str>) -> String {
+    args.iter().map(|x| quote(x)).collect::<Vec<_>>().join(" ")
+}
+
+/// Whether `script` reads the same in fish, like `cargo build --release`.
+fn is_plain(script: &str) -> bool {
+    script.split(' ').all(is_plain_word)
+}
+
+impl Emit {
+    /// Writes the next command as `line` instead of its program and
+    /// arguments, and lets the commands after it run when it fails if
+    /// `continue_on_error` is set.
+    pub(super) fn next_as(&mut self, line: String, continue_on_error: bool) {
+        self.next = Some((line, continue_on_error));
+    }
+
+    pub(super) fn push_command(&mut self, command: &process::Command) {
+        let env = command
+            .get_envs()
+            .filter_map(|(key, value)| {
+                let value = value?.to_string_lossy().into_owned();
+                Some((key.to_string_lossy().into_owned(), value))
+            })
+            .collect();
+        let args = [command.get_program()]
+            .into_iter()
+            .chain(command.get_args())
+            .map(|x| x.to_string_lossy().into_owned())
+            .collect();
+        let (line, continue_on_error) = match self.next.take() {
+            Some((script, continue_on_error)) => (Line::Script(script), continue_on_error),
+            None => (Line::Argv(args), false),
+        };
+        // commands without a directory run where humsh is now
+        let dir = command
+            .get_current_dir()
+            .map(Path::to_owned)
+            .or_else(|| std::env::current_dir().ok());
+        self.commands.push(Emitted {
+            dir,
+            env,
+            line,
+            continue_on_error,
+        });
+    }
+
+    /// Writes the commands, joined so they stop at the first failure like
+    /// they would have in humsh.
+    pub(super) fn finish(mut self) -> anyhow::Result<()> {
+        let Some(last) = self.commands.pop() else {
+            return Ok(());
+        };
+        let mut joined = last.to_shell(&self.start_dir, self.shell);
+        // whether `joined` has to be grouped to follow a `&&`
+        let mut sequence = false;
+        for command in self.commands.iter().rev() {
+            let line = command.to_shell(&self.start_dir, self.shell);
+            if command.continue_on_error {
+                joined = format!("{line}; {joined}");
+                sequence = true;
+            } else if sequence {
+                joined = match self.shell {
+                    Shell::Posix => format!("{line} && {{ {joined}; }}"),
+                    Shell::Fish => format!("{line} && begin; {joined}; end"),
+                };
+                sequence = false;
+            } else {
+                joined = format!("{line} && {joined}");
+            }
+        }
+        self.out.write_all(joined.as_bytes())?;
+        Ok(())
+    }
+}
+
+impl Ui {
+    /// Emits commands to the file descriptor `fd` instead of running them.
+    /// `start_dir` is where the shell that started humsh is.
+    pub fn with_emit(mut self, fd: u32, start_dir: PathBuf, shell: Shell) -> anyhow::Result<Self> {
+        let out = OpenOptions::new()
+            .write(true)
+            .open(format!("/dev/fd/{fd}"))
+            .with_context(|| format!("file descriptor {fd} is not open for writing"))?;
+        self.emit = Some(Emit {
+            out,
+            start_dir,
+            shell,
+            commands: Vec::new(),
+            next: None,
+        });
+        Ok(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Seek};
+
+    use super::*;
+
+    fn argv(dir: &str, args: &[&str]) -> Emitted {
+        Emitted {
+            dir: Some(dir.into()),
+            env: Vec::new(),
+            line: Line::Argv(args.iter().map(|x| x.to_string()).collect()),
+            continue_on_error: false,
+        }
+    }
+
+    fn script(script: &str, continue_on_error: bool) -> Emitted {
+        Emitted {
+            dir: Some("/repo".into()),
+            env: Vec::new(),
+            line: Line::Script(script.to_owned()),
+            continue_on_error,
+        }
+    }
+
+    fn finish(shell: Shell, commands: Vec<Emitted>) -> String {
+        let mut out = tempfile::tempfile().unwrap();
+        let emit = Emit {
+            out: out.try_clone().unwrap(),
+            start_dir: "/repo".into(),
+            shell,
+            commands,
+            next: None,
+        };
+        emit.finish().unwrap();
+        let mut text = String::new();
+        out.rewind().unwrap();
+        out.read_to_string(&mut text).unwrap();
+        text
+    }
+
+    #[test]
+    fn cds_in_a_subshell_only_elsewhere() {
+        let here = argv("/repo", &["cargo", "test", "a b"]);
+        assert_eq!(
+            here.to_shell(Path::new("/repo"), Shell::Posix),
+            "cargo test 'a b'"
+        );
+        let there = argv("/repo/sub dir", &["make"]);
+        assert_eq!(
+            there.to_shell(Path::new("/repo"), Shell::Posix),
+            "(cd '/repo/sub dir' && make)"
+        );
+        assert_eq!(
+            there.to_shell(Path::new("/repo"), Shell::Fish),
+            r"bash -c 'cd \'/repo/sub dir\' && make'"
+        );
+    }
+
+    #[test]
+    fn exports_the_environment() {
+        let mut command = argv("/repo", &["cargo", "run"]);
+        command.env = vec![("RUST_LOG".to_owned(), "debug info".to_owned())];
+        assert_eq!(
+            command.to_shell(Path::new("/repo"), Shell::Posix),
+            "(export RUST_LOG='debug info' && cargo run)"
+        );
+    }
+
+    #[test]
+    fn keeps_scripts_together() {
+        let start_dir = Path::new("/repo");
+        assert_eq!(
+            script("make; make install", false).to_shell(start_dir, Shell::Posix),
+            "(make; make install)"
+        );
+        assert_eq!(
+            script("cargo build --release", false).to_shell(start_dir, Shell::Fish),
+            "cargo build --release"
+        );
+        assert_eq!(
+            script("echo $(date)", false).to_shell(start_dir, Shell::Fish),
+            "bash -c 'echo $(date)'"
+        );
+    }
+
+    #[test]
+    fn quotes_arguments_for_fish() {
+        let command = argv("/repo", &["grep", r"a\b", "it's"]);
+        assert_eq!(
+            command.to_shell(Path::new("/repo"), Shell::Fish),
+            r"grep 'a\\b' 'it\'s'"
+        );
+    }
+
+    #[test]
+    fn stops_at_the_first_failure() {
+        let commands = || vec![script("a", false), script("b", false), script("c", false)];
+        assert_eq!(finish(Shell::Posix, commands()), "a && b && c");
+        assert_eq!(finish(Shell::Fish, commands()), "a && b && c");
+        assert_eq!(finish(Shell::Posix, Vec::new()), "");
+    }
+
+    #[test]
+    fn groups_steps_after_one_that_may_fail() {
+        let commands = || vec![script("a", false), script("b", true), script("c", false)];
+        assert_eq!(finish(Shell::Posix, commands()), "a && { b; c; }");
+        assert_eq!(finish(Shell::Fish, commands()), "a && begin; b; c; end");
+        let commands = vec![script("a", true), script("b", false)];
+        assert_eq!(finish(Shell::Posix, commands), "a; b");
+    }
+}
@@ -0,0 +1,281 @@
+use std::fmt;
+use std::io::{Read, Write};
+use std::process::{self, Stdio};
+use std::sync::Arc;
+
+use crossterm::event::{KeyCode, KeyEvent};
+use crossterm::{queue, style::*};
+
+use crate::data::ButtonHandler;
+use crate::util::base64;
+
+use super::{display_command, Context, NextLine, Stdout, Ui};
+
+/// How much of the stderr of a failed command is kept.
+const STDERR_LIMIT: usize = 64 * 1024;
+
+/// A foreground command that exited with an error. Kept in the error chain so
+/// the error panel can show it and run it again.
+#[derive(Debug)]
+pub struct CommandFailed {
+    pub command: process::Command,
+    pub code: Option<i32>,
+    /// The tail of what the command wrote to stderr, when it was captured.
+    pub stderr: Option<String>,
+}
+
+impl fmt::Display for CommandFailed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "exit code {}", self.code.unwrap_or(-1))
+    }
+}
+
+impl std::error::Error for CommandFailed {}
+
+/// Runs `command` to completion. With `capture_stderr` its stderr still goes
+/// to the terminal, but is also kept for the error panel.
+pub fn run_foreground(command: &mut process::Command, capture_stderr: bool) -> anyhow::Result<()> {
+    if !capture_stderr {
+        let status = command.spawn()?.wait()?;
+        return check(command, status, None);
+    }
+    let mut child = command.stderr(Stdio::piped()).spawn()?;
+    let mut pipe = child.stderr.take().expect("stderr is piped");
+    let reader = std::thread::spawn(move || {
+        let mut captured = Vec::new();
+        let mut buf = [0; 4096];
+        let mut stderr = std::io::stderr();
+        while let Ok(n @ 1..) = pipe.read(&mut buf) {
+            stderr.write_all(&buf[..n]).ok();
+            captured.extend_from_slice(&buf[..n]);
+            if captured.len() > STDERR_LIMIT {
+                captured.drain(..captured.len() - STDERR_LIMIT);
+            }
+        }
+        captured
+    });
+    let status = child.wait()?;
+    let captured = reader.join().unwrap_or_default();
+    check(
+        command,
+        status,
+        Some(String::from_utf8_lossy(&captured).into_owned()),
+    )
+}
+
+fn check(
+    command: &process::Command,
+    status: process::ExitStatus,
+    stderr: Option<String>,
+) -> anyhow::Result<()> {
+    if status.success() {
+        return Ok(());
+    }
+    Err(CommandFailed {
+        command: copy_command(command),
+        code: status.code(),
+        stderr,
+    }
+    .into())
+}
+
+/// `process::Command` is not `Clone`.
+fn copy_command(command: &process::Command) -> process::Command {
+    let mut copy = process::Command::new(command.get_program());
+    copy.args(command.get_args());
+    if let Some(dir) = command.get_current_dir() {
+        copy.current_dir(dir);
+    }
+    for (key, value) in command.get_envs() {
+        match value {
+            Some(value) => copy.env(key, value),
+            None => copy.env_remove(key),
+        };
+    }
+    copy
+}
+
+/// An error from a button, shown in place of the page until dismissed.
+pub(super) struct ErrorPanel {
+    error: anyhow::Error,
+    /// The button that failed, run again by retry when no command failed.
+    callback: Arc<dyn ButtonHandler>,
+    showing_stderr: bool,
+}
+
+impl ErrorPanel {
+    pub(super) fn new(error: anyhow::Error, callback: Arc<dyn ButtonHandler>) -> Self {
+        ErrorPanel {
+            error,
+            callback,
+            showing_stderr: false,
+        }
+    }
+
+    fn failed(&self) -> Option<&CommandFailed> {
+        self.error.chain().find_map(|x| x.downcast_ref())
+    }
+
+    fn stderr(&self) -> Option<&str> {
+        self.failed()
+            .and_then(|x| x.stderr.as_deref())
+            .filter(|x| !x.trim().is_empty())
+    }
+
+    /// Key and description of the actions that apply to this error.
+    fn actions(&self) -> Vec<(&'static str, &'static str)> {
+        let mut actions = vec![("r", "Retry")];
+        if self.failed().is_some() {
+            actions.push(("c", "Copy command"));
+        }
+        if self.stderr().is_some() {
+            actions.push((
+                "s",
+                if self.showing_stderr {
+                    "Hide stderr"
+                } else {
+                    "Show stderr"
+                },
+            ));
+        }
+        actions.extend([("`", "Output"), ("esc", "Dismiss")]);
+        actions
+    }
+}
+
+impl Ui {
+    pub(super) fn show_error(&mut self, error: anyhow::Error, callback: Arc<dyn ButtonHandler>) {
+        self.error_panel = Some(ErrorPanel::new(error, callback));
+        self.showing_cmd = false;
+    }
+
+    pub(super) fn handle_error_key(
+        &mut self,
+        key: KeyEvent,
+    ) -> anyhow::Result<Option<Arc<dyn ButtonHandler>>> {
+        let Some(panel) = &mut self.error_panel else {
+            return Ok(None);
+        };
+        let callback: Arc<dyn ButtonHandler> = match key.code {
+            KeyCode::Esc | KeyCode::Enter | KeyCode::Char('q') => {
+                self.error_panel = None;
+                return Ok(None);
+            }
+            KeyCode::Char('s') if panel.stderr().is_some() => {
+                panel.showing_stderr = !panel.showing_stderr;
+                return Ok(None);
+            }
+            KeyCode::Char('`') => Arc::new(|mut ctx: Context| ctx.toggle_cmd()),
+            KeyCode::Char('r') => {
+                let panel = self.error_panel.take().expect("checked above");
+                match panel.failed() {
+                    Some(failed) => {
+                        let command = copy_command(&failed.command);
+                        let capture_stderr = failed.stderr.is_some();
+                        Arc::new(move |mut ctx: Context| {
+                            ctx.rerun_command(&mut copy_command(&command), capture_stderr)
+                        })
+                    }
+                    None => panel.callback,
+                }
+            }
+            KeyCode::Char('c') => {
+                let Some(failed) = panel.failed() else {
+                    return Ok(None);
+                };
+                let text = display_command(&failed.command);
+                Arc::new(move |mut ctx: Context| ctx.copy_to_clipboard(&text))
+            }
+            _ => return Ok(None),
+        };
+        Ok(Some(callback))
+    }
+
+    pub(super) fn draw_error_panel(
+        &self,
+        panel: &ErrorPanel,
+        stdout: Stdout,
+    ) -> anyhow::Result<()> {
+        queue!(
+            stdout,
+            PrintStyledContent(self.style.heading.apply("Error")),
+            NextLine
+        )?;
+        for (i, cause) in panel.error.chain().enumerate() {
+            let line = match i {
+                0 => format!(" ! {cause}"),
+                _ => format!("   caused by: {cause}"),
+            };
+            for (j, line) in line.lines().enumerate() {
+                let indent = if j == 0 { "" } else { "   " };
+                queue!(
+                    stdout,
+                    PrintStyledContent(self.style.error.apply(format!("{indent}{line}"))),
+                    NextLine
+                )?;
+            }
+        }
+        if let Some(failed) = panel.failed() {
+            queue!(
+                stdout,
+                NextLine,
+                Print(" Command   "),
+                PrintStyledContent(self.style.command.apply(display_command(&failed.command))),
+                NextLine,
+                Print(" Exit code "),
+                Print(failed.code.map_or("none".into(), |x| x.to_string())),
+                NextLine,
+            )?;
+        }
+        if let Some(stderr) = panel.stderr().filter(|_| panel.showing_stderr) {
+            // what fits between the panel and the prompt
+            let (_, height) = stdout.size()?;
+            let room = (height as usize).saturating_sub(12).max(3);
+            let lines = stderr.lines().collect::<Vec<_>>();
+            queue!(
+                stdout,
+                NextLine,
+                PrintStyledContent(self.style.heading.apply("Stderr")),
+                NextLine
+            )?;
+            for line in &lines[lines.len().saturating_sub(room)..] {
+                queue!(stdout, Print(" "), Print(line), NextLine)?;
+            }
+        }
+        queue!(stdout, NextLine)?;
+        for (key, description) in panel.actions() {
+            queue!(
+                stdout,
+                Print(" "),
+                PrintStyledContent(self.style.button.apply(key)),
+                Print(" "),
+                Print(description),
+                Print(" ")
+            )?;
+        }
+        queue!(stdout, NextLine, NextLine)?;
+        Ok(())
+    }
+}
+
+impl<'a, 'b> Context<'a, 'b> {
+    /// Runs a command from the error panel again.
+    fn rerun_command(
+        &mut self,
+        command: &mut process::Command,
+        capture_stderr: bool,
+    ) -> anyhow::Result<()> {
+        self.leave_ui()?;
+        self.show_cmd()?;
+        self.hint_running_command(&display_command(command))?;
+        run_foreground(command, capture_stderr)
+    }
+
+    /// Copies `text` with the OSC 52 escape sequence, which most terminals
+    /// and tmux understand.
+    fn copy_to_clipboard(&mut self, text: &str) -> anyhow::Result<()> {
+        write!(self.stdout, "\x1b]52;c;{}\x07", base64(text.as_bytes()))?;
+        self.stdout.flush()?;
+        Ok(())
+    }
+}
@@ -9,6 +9,8 @@ use super::Context;
 #[derive(Debug, Clone, Default)]
 pub struct KeyHandler {
     current_keys: String,
+    /// The keys of the last press that matched nothing, until the next one.
+    unbound: Option<String>,
 }
 
 impl KeyHandler {
@@ -20,6 +22,10 @@ impl KeyHandler {
         &self.current_keys
     }
 
+    pub fn unbound(&self) -> Option<&str> {
+        self.unbound.as_deref()
+    }
+
     fn reset(&mut self) {
         self.current_keys.clear();
     }
@@ -29,12 +35,22 @@ impl KeyHandler {
         key: KeyEvent,
         bindings: impl Iterator<Item = (&'a Keybind, &'a Arc<dyn ButtonHandler>)>,
     ) -> anyhow::Result<Option<Arc<dyn ButtonHandler>>> {
+        self.unbound = None;
         let key = match key.code {
             KeyCode::Char('`') => {
                 self.reset();
                 return Ok(Some(Arc::new(|mut ctx: Context| ctx.toggle_cmd())));
             }
             KeyCode::Char(c) => c,
+            KeyCode::Esc | KeyCode::Backspace if !self.current_keys.is_empty() => {
+                // step back out of a partial sequence instead of leaving the page
+                if key.code == KeyCode::Esc {
+                    self.reset();
+                } else {
+                    self.current_keys.pop();
+                }
+                return Ok(None);
+            }
             KeyCode::Esc | KeyCode::F(9) => {
                 self.reset();
                 return Ok(Some(Arc::new(|mut ctx: Context| {
@@ -48,7 +64,11 @@ impl KeyHandler {
                     Ok(())
                 })));
             }
-            _ => return Ok(None),
+            code => {
+                self.unbound = Some(format!("{}{}", self.current_keys, key_name(code)));
+                self.reset();
+                return Ok(None);
+            }
         };
 
         self.current_keys.push(key);
@@ -60,7 +80,116 @@ impl KeyHandler {
                 return Ok(None);
             }
         }
-        self.reset();
+        self.unbound = Some(std::mem::take(&mut self.current_keys));
         Ok(None)
     }
 }
+
+/// `c` for characters, `<enter>` and the like for the rest.
+pub fn key_name(code: KeyCode) -> String {
+    let name = match code {
+        KeyCode::Char(c) => return c.to_string(),
+        KeyCode::F(n) => return format!("<f{n}>"),
+        KeyCode::Enter => "enter",
+        KeyCode::Esc => "esc",
+        KeyCode::Tab => "tab",
+        KeyCode::BackTab => "backtab",
+        KeyCode::Backspace => "backspace",
+        KeyCode::Delete => "delete",
+        KeyCode::Insert => "insert",
+        KeyCode::Up => "up",
+        KeyCode::Down => "down",
+        KeyCode::Left => "left",
+        KeyCode::Right => "right",
+        KeyCode::Home => "home",
+        KeyCode::End => "end",
+        KeyCode::PageUp => "pageup",
+        KeyCode::PageDown => "pagedown",
+        _ => return format!("<{code:?}>").to_lowercase(),
+    };
+    format!("<{name}>")
+}
+
+#[cfg(test)]
+mod tests {
+    use crossterm::event::KeyModifiers;
+
+    use super::*;
+    use crate::data::{button, Button};
+
+    struct Keys {
+        handler: KeyHandler,
+        buttons: Vec<Button>,
+    }
+
+    impl Keys {
+        fn new(keys: &[&str]) -> Self {
+            Self {
+                handler: KeyHandler::new(),
+                buttons: keys.iter().map(|x| button(*x, *x, |_| Ok(()))).collect(),
+            }
+        }
+
+        /// The key of the button that `code` ran, if any.
+        fn press(&mut self, code: KeyCode) -> Option<String> {
+            let action = self
+                .handler
+                .handle_key(
+                    KeyEvent::new(code, KeyModifiers::NONE),
+                    self.buttons.iter().map(|x| (&x.key, &x.handler)),
+                )
+                .unwrap()?;
+            let button = self
+                .buttons
+                .iter()
+                .find(|x| Arc::ptr_eq(&x.handler, &action));
+            Some(button.map_or("<builtin>".to_owned(), |x| x.key.0.clone()))
+        }
+
+        fn type_keys(&mut self, keys: &str) -> Option<String> {
+            keys.chars().map(|c| self.press(KeyCode::Char(c))).last()?
+        }
+    }
+
+    #[test]
+    fn accumulates_prefixes() {
+        let mut keys = Keys::new(&["a", "gb", "gc"]);
+        assert_eq!(keys.type_keys("a"), Some("a".to_owned()));
+        assert_eq!(keys.type_keys("g"), None);
+        assert_eq!(keys.handler.prefix(), "g");
+        assert_eq!(keys.type_keys("c"), Some("gc".to_owned()));
+        assert_eq!(keys.handler.prefix(), "");
+    }
+
+    #[test]
+    fn backspace_and_esc_step_out_of_a_prefix() {
+        let mut keys = Keys::new(&["gb", "gcd"]);
+        keys.type_keys("gc");
+        assert_eq!(keys.press(KeyCode::Backspace), None);
+        assert_eq!(keys.handler.prefix(), "g");
+        assert_eq!(keys.type_keys("b"), Some("gb".to_owned()));
+
+        keys.type_keys("gc");
+        assert_eq!(keys.press(KeyCode::Esc), None);
+        assert_eq!(keys.handler.prefix(), "");
+        // without a prefix they leave the page
+        assert_eq!(keys.press(KeyCode::Esc), Some("<builtin>".to_owned()));
+        assert_eq!(keys.press(KeyCode::Backspace), None);
+        assert_eq!(keys.handler.unbound(), Some("<backspace>"));
+    }
+
+    #[test]
+    fn reports_unbound_keys() {
+        let mut keys = Keys::new(&["a", "gb"]);
+        assert_eq!(keys.type_keys("gx"), None);
+        assert_eq!(keys.handler.unbound(), Some("gx"));
+        assert_eq!(keys.handler.prefix(), "");
+        assert_eq!(keys.type_keys("a"), Some("a".to_owned()));
+        assert_eq!(keys.handler.unbound(), None);
+
+        keys.type_keys("g");
+        assert_eq!(keys.press(KeyCode::Enter), None);
+        assert_eq!(keys.handler.unbound(), Some("g<enter>"));
+        assert_eq!(keys.handler.prefix(), "");
+    }
+}
@@ -0,0 +1,197 @@
+use std::ops::Range;
+
+use crossterm::{cursor, queue, style::*};
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+use super::{NextLine, Stdout};
+
+/// Space between two columns.
+const GAP: usize = 3;
+/// Columns are narrowed, cutting their longest lines, to fit more of them,
+/// but not below this.
+const MIN_COLUMN_WIDTH: usize = 24;
+
+/// Styled text drawn on one row.
+pub(super) type Line = Vec<StyledContent<String>>;
+
+/// Columns the line takes up on the terminal.
+fn line_width(line: &Line) -> usize {
+    line.iter().map(|x| x.content().width()).sum()
+}
+
+/// Cuts `line` to `width` columns, ending in `…` when something was cut.
+fn truncate(line: Line, width: usize) -> Line {
+    if line_width(&line) <= width {
+        return line;
+    }
+    let mut room = width.saturating_sub(1);
+    let mut truncated = Line::new();
+    for part in line {
+        let mut text = String::new();
+        for ch in part.content().chars() {
+            let ch_width = ch.width().unwrap_or(0);
+            if ch_width > room {
+                room = 0;
+                break;
+            }
+            room -= ch_width;
+            text.push(ch);
+        }
+        let style = *part.style();
+        truncated.push(StyledContent::new(style, text));
+        if room == 0 {
+            truncated.push(StyledContent::new(style, "…".to_owned()));
+            break;
+        }
+    }
+    truncated
+}
+
+/// Splits the blocks, kept in order, into at most `count` columns of about the
+/// same height.
+fn split(heights: &[usize], count: usize) -> Vec<Range<usize>> {
+    // blocks in a column are separated by an empty line
+    let total = heights.iter().sum::<usize>() + heights.len().saturating_sub(1);
+    let target = total.div_ceil(count);
+    let mut columns = Vec::new();
+    let mut start = 0;
+    let mut height = 0;
+    for (i, block) in heights.iter().enumerate() {
+        let with_block = if i == start {
+            *block
+        } else {
+            height + 1 + block
+        };
+        if i > start && with_block > target && columns.len() + 1 < count {
+            columns.push(start..i);
+            start = i;
+            height = *block;
+        } else {
+            height = with_block;
+        }
+    }
+    columns.push(start..heights.len());
+    columns
+}
+
+/// The width of the widest line in each column.
+fn column_widths(blocks: &[Vec<Line>], columns: &[Range<usize>]) -> Vec<usize> {
+    columns
+        .iter()
+        .map(|range| {
+            blocks[range.clone()]
+                .iter()
+                .flatten()
+                .map(line_width)
+                .max()
+                .unwrap_or(0)
+        })
+        .collect()
+}
+
+/// Narrows the widest columns until they all fit in `width`, `None` when that
+/// would take them below `min`.
+fn fit(widths: &[usize], width: usize, min: usize) -> Option<Vec<usize>> {
+    let mut room = width.checked_sub(GAP * (widths.len() - 1))?;
+    let mut sorted = widths.to_vec();
+    sorted.sort_unstable();
+    // the widest a column may be
+    let mut limit = usize::MAX;
+    for (i, column) in sorted.iter().enumerate() {
+        let share = room / (sorted.len() - i);
+        if *column > share {
+            limit = share;
+            break;
+        }
+        room -= column;
+    }
+    if limit < min {
+        return None;
+    }
+    Some(widths.iter().map(|x| (*x).min(limit)).collect())
+}
+
+/// Packs `blocks` into as many columns as fit in `width` and draws them,
+/// cutting lines that are too long for their column.
+pub(super) fn draw_columns(
+    blocks: Vec<Vec<Line>>,
+    width: u16,
+    stdout: Stdout,
+) -> std::io::Result<()> {
+    let width = width as usize;
+    let heights = blocks.iter().map(Vec::len).collect::<Vec<_>>();
+    let (columns, widths) = (2..=blocks.len())
+        .rev()
+        .find_map(|count| {
+            let columns = split(&heights, count);
+            let widths = fit(&column_widths(&blocks, &columns), width, MIN_COLUMN_WIDTH)?;
+            Some((columns, widths))
+        })
+        .unwrap_or_else(|| {
+            let columns = split(&heights, 1);
+            let widths = fit(&column_widths(&blocks, &columns), width, 0).unwrap_or_default();
+            (columns, widths)
+        });
+
+    // the lines of each column, `None` between blocks
+    let mut cells = columns
+        .iter()
+        .zip(&widths)
+        .map(|(range, width)| {
+            let mut lines = Vec::new();
+            for block in &blocks[range.clone()] {
+                if !lines.is_empty() {
+                    lines.push(None);
+                }
+                lines.extend(block.iter().map(|x| Some(truncate(x.clone(), *width))));
+            }
+            lines.into_iter()
+        })
+        .collect::<Vec<_>>();
+    let rows = cells.iter().map(|x| x.len()).max().unwrap_or(0);
+    for _ in 0..rows {
+        let mut x = 0;
+        for (column, lines) in cells.iter_mut().enumerate() {
+            if let Some(Some(line)) = lines.next() {
+                queue!(stdout, cursor::MoveToColumn(x as u16))?;
+                for part in line {
+                    queue!(stdout, PrintStyledContent(part))?;
+                }
+            }
+            x += widths[column] + GAP;
+        }
+        queue!(stdout, NextLine)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_balances_columns() {
+        assert_eq!(split(&[3, 3, 3, 3], 2), [0..2, 2..4]);
+        assert_eq!(split(&[10, 1, 1, 1], 2), [0..1, 1..4]);
+        assert_eq!(split(&[2, 2], 1), vec![0..2]);
+        // never more columns than blocks
+        assert_eq!(split(&[2], 3), vec![0..1]);
+    }
+
+    #[test]
+    fn fit_narrows_the_widest() {
+        assert_eq!(fit(&[10, 20], 40, 0), Some(vec![10, 20]));
+        assert_eq!(fit(&[10, 40], 40, 0), Some(vec![10, 27]));
+        assert_eq!(fit(&[40, 40], 40, 0), Some(vec![18, 18]));
+        assert_eq!(fit(&[40, 40], 40, 24), None);
+        assert_eq!(fit(&[1, 1], 2, 0), None);
+    }
+
+    #[test]
+    fn truncate_ends_in_ellipsis() {
+        let line = vec![StyledContent::new(ContentStyle::new(), "abcdef".to_owned())];
+        let text = |line: Line| line.iter().map(|x| x.content().clone()).collect::<String>();
+        assert_eq!(text(truncate(line.clone(), 6)), "abcdef");
+        assert_eq!(text(truncate(line, 4)), "abc…");
+    }
+}
@@ -0,0 +1,159 @@
+use std::process;
+
+use anyhow::{bail, Context as _};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+use crate::util::{shell_quote, CheckExitStatus};
+
+use super::input::key_name;
+use super::{Backend, Context, Terminal, Ui};
+
+/// Set when the ui is driven by `--keys` instead of a terminal.
+#[derive(Debug, Default)]
+pub(super) struct Script {
+    /// Run commands instead of printing them.
+    exec: bool,
+    /// Every command the keys ran, in order.
+    commands: Vec<String>,
+}
+
+impl Script {
+    /// Records `command`, returns whether it should also be run.
+    fn record(&mut self, command: String) -> bool {
+        self.commands.push(command);
+        self.exec
+    }
+}
+
+/// Splits `keys` into key presses, `<esc>` is the escape key.
+pub fn parse_keys(keys: &str) -> Vec<KeyEvent> {
+    let mut events = Vec::new();
+    let mut rest = keys;
+    while let Some(c) = rest.chars().next() {
+        if let Some(after) = rest.strip_prefix("<esc>") {
+            events.push(KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE));
+            rest = after;
+        } else {
+            events.push(KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE));
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+    events
+}
+
+/// `command` as it would be typed in a shell, including its directory and
+/// environment.
+pub fn display_command(command: &process::Command) -> String {
+    let mut words = Vec::new();
+    if let Some(dir) = command.get_current_dir() {
+        words.push(format!("cd {} &&", shell_quote(&dir.to_string_lossy())));
+    }
+    for (key, value) in command.get_envs() {
+        if let Some(value) = value {
+            let value = value.to_string_lossy();
+            words.push(format!("{}={}", key.to_string_lossy(), shell_quote(&value)));
+        }
+    }
+    words.extend(
+        [command.get_program()]
+            .into_iter()
+            .chain(command.get_args())
+            .map(|x| shell_quote(&x.to_string_lossy()).into_owned()),
+    );
+    words.join(" ")
+}
+
+impl Ui {
+    /// Feeds keys through the same bindings as the terminal ui, without a
+    /// terminal. Commands the buttons would run are printed, or run with
+    /// `exec`. When no button ran anything the command line the keys built
+    /// is used instead.
+    pub fn with_script(mut self, exec: bool) -> Self {
+        self.script = Some(Script {
+            exec,
+            commands: Vec::new(),
+        });
+        self.multi_term = None;
+        self.config_watcher = None;
+        self.window_watcher = None;
+        self
+    }
+
+    pub fn run_script(mut self, keys: &str) -> anyhow::Result<()> {
+        let mut terminal = Terminal::stdout();
+        let mut stdout: &mut dyn Backend = &mut terminal;
+        let mut typed = String::new();
+        for key in parse_keys(keys) {
+            typed.push_str(&key_name(key.code));
+            let Some(callback) = self.handle_key(key)? else {
+                if let Some(keys) = self.key_handler.unbound() {
+                    bail!("no binding for `{keys}`");
+                }
+                continue;
+            };
+            let mut exit = false;
+            let ctx = Context {
+                stdout: &mut stdout,
+                ui: &mut self,
+                exit: &mut exit,
+            };
+            callback
+                .run(ctx)
+                .with_context(|| format!("after keys `{typed}`"))?;
+            if exit {
+                break;
+            }
+        }
+        if !self.key_handler.prefix().is_empty() {
+            bail!("keys `{typed}` end in the middle of a binding");
+        }
+        if self.script().commands.is_empty() {
+            let cmd = self.command_line().clone();
+            if cmd.args.is_empty() {
+                bail!("keys `{typed}` did not build a command");
+            }
+            if self.script_record(|| cmd.to_shell()) {
+                self.run_command_line_other(&cmd, &mut stdout)?;
+            }
+        }
+        let script = self.script.take().expect("running a script");
+        if !script.exec {
+            for command in script.commands {
+                println!("{command}");
+            }
+        }
+        Ok(())
+    }
+
+    fn script(&self) -> &Script {
+        self.script.as_ref().expect("running a script")
+    }
+
+    pub(super) fn is_scripted(&self) -> bool {
+        self.script.is_some()
+    }
+
+    /// Returns whether `command` should be run, always true outside of
+    /// scripts.
+    pub(super) fn script_record(&mut self, command: impl FnOnce() -> String) -> bool {
+        match &mut self.script {
+            Some(script) => script.record(command()),
+            None => true,
+        }
+    }
+}
+
+impl<'a, 'b> Context<'a, 'b> {
+    /// Runs a background command to completion when scripted, as nothing
+    /// would wait for it otherwise.
+    pub(super) fn run_script_background(&mut self, name: &str, mut command: process::Command) {
+        let result = command
+            .stdin(process::Stdio::null())
+            .status()
+            .map_err(anyhow::Error::from)
+            .and_then(|x| x.check_exit_status());
+        if let Err(e) = result {
+            eprintln!("! {name}: {e:#}");
+        }
+    }
+}
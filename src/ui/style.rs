@@ -1,4 +1,4 @@
-use crossterm::style::{Color, ContentStyle, StyledContent, Stylize};
+use crossterm::style::{Attribute, Color, ContentStyle, Stylize};
 
 pub struct Style {
     pub heading: ContentStyle,
@@ -10,7 +10,122 @@ pub struct Style {
     pub command: ContentStyle,
     pub flag_off: ContentStyle,
     pub flag_on: ContentStyle,
-    pub prompt_char: StyledContent<&'static str>,
+    pub prompt: ContentStyle,
+    pub prompt_char: String,
+}
+
+/// The `[theme]` section of the user config. Unset fields come from the
+/// preset.
+#[derive(Debug, Clone, Default, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Theme {
+    #[serde(default)]
+    pub preset: Preset,
+    pub heading: Option<StyleSpec>,
+    pub normal: Option<StyleSpec>,
+    pub button: Option<StyleSpec>,
+    pub status: Option<StyleSpec>,
+    pub directory: Option<StyleSpec>,
+    pub error: Option<StyleSpec>,
+    pub command: Option<StyleSpec>,
+    pub flag_off: Option<StyleSpec>,
+    pub flag_on: Option<StyleSpec>,
+    pub prompt: Option<StyleSpec>,
+    pub prompt_char: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Preset {
+    #[default]
+    Dark,
+    Light,
+    HighContrast,
+}
+
+/// Either a string like `"cyan bold"` or `"#ff8800 on 236"`, or a table with
+/// `fg`, `bg`, `bold`, `italic` and `underline`.
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(try_from = "RawStyleSpec")]
+pub struct StyleSpec(ContentStyle);
+
+#[derive(serde::Deserialize)]
+#[serde(untagged)]
+enum RawStyleSpec {
+    String(String),
+    Table {
+        fg: Option<String>,
+        bg: Option<String>,
+        #[serde(default)]
+        bold: bool,
+        #[serde(default)]
+        italic: bool,
+        #[serde(default)]
+        underline: bool,
+    },
+}
+
+impl TryFrom<RawStyleSpec> for StyleSpec {
+    type Error = String;
+
+    fn try_from(raw: RawStyleSpec) -> Result<Self, Self::Error> {
+        let mut style = ContentStyle::new();
+        match raw {
+            RawStyleSpec::String(spec) => {
+                let mut words = spec.split_whitespace();
+                while let Some(word) = words.next() {
+                    match word {
+                        "bold" => style.attributes.set(Attribute::Bold),
+                        "italic" => style.attributes.set(Attribute::Italic),
+                        "underline" => style.attributes.set(Attribute::Underlined),
+                        "on" => {
+                            let color = words.next().ok_or("expected a color after `on`")?;
+                            style.background_color = Some(parse_color(color)?);
+                        }
+                        color => style.foreground_color = Some(parse_color(color)?),
+                    }
+                }
+            }
+            RawStyleSpec::Table {
+                fg,
+                bg,
+                bold,
+                italic,
+                underline,
+            } => {
+                style.foreground_color = fg.as_deref().map(parse_color).transpose()?;
+                style.background_color = bg.as_deref().map(parse_color).transpose()?;
+                for (set, attribute) in [
+                    (bold, Attribute::Bold),
+                    (italic, Attribute::Italic),
+                    (underline, Attribute::Underlined),
+                ] {
+                    if set {
+                        style.attributes.set(attribute);
+                    }
+                }
+            }
+        }
+        Ok(StyleSpec(style))
+    }
+}
+
+/// Accepts color names like `dark_cyan`, 256 color indices and `#rrggbb`.
+fn parse_color(value: &str) -> Result<Color, String> {
+    if let Some(hex) = value.strip_prefix('#') {
+        let channel = |i: usize| {
+            hex.get(i..i + 2)
+                .and_then(|x| u8::from_str_radix(x, 16).ok())
+        };
+        return match (hex.len(), channel(0), channel(2), channel(4)) {
+            (6, Some(r), Some(g), Some(b)) => Ok(Color::Rgb { r, g, b }),
+            _ => Err(format!("invalid hex color `{value}`")),
+        };
+    }
+    if let Ok(index) = value.parse::<u8>() {
+        return Ok(Color::AnsiValue(index));
+    }
+    Color::try_from(value).map_err(|()| format!("unknown color `{value}`"))
 }
 
 pub fn builtin() -> Style {
@@ -24,6 +139,93 @@ pub fn builtin() -> Style {
         command: ContentStyle::new().with(Color::DarkGreen),
         flag_off: ContentStyle::new().with(Color::DarkGrey),
         flag_on: ContentStyle::new().with(Color::Cyan),
-        prompt_char: " λ ".with(Color::Yellow),
+        prompt: ContentStyle::new().with(Color::Yellow),
+        prompt_char: " λ ".into(),
+    }
+}
+
+pub fn light() -> Style {
+    Style {
+        heading: ContentStyle::new().with(Color::DarkBlue),
+        normal: ContentStyle::new(),
+        button: ContentStyle::new().with(Color::Black),
+        status: ContentStyle::new().with(Color::DarkMagenta),
+        directory: ContentStyle::new().with(Color::DarkCyan),
+        error: ContentStyle::new().with(Color::DarkRed),
+        command: ContentStyle::new().with(Color::DarkGreen),
+        flag_off: ContentStyle::new().with(Color::Grey),
+        flag_on: ContentStyle::new().with(Color::DarkCyan),
+        prompt: ContentStyle::new().with(Color::DarkYellow),
+        prompt_char: " λ ".into(),
+    }
+}
+
+pub fn high_contrast() -> Style {
+    Style {
+        heading: ContentStyle::new().with(Color::White).bold().underlined(),
+        normal: ContentStyle::new(),
+        button: ContentStyle::new().with(Color::Yellow).bold(),
+        status: ContentStyle::new().with(Color::Magenta).bold(),
+        directory: ContentStyle::new().with(Color::Cyan).bold(),
+        error: ContentStyle::new().with(Color::Red).bold(),
+        command: ContentStyle::new().with(Color::Green).bold(),
+        flag_off: ContentStyle::new().with(Color::Grey),
+        flag_on: ContentStyle::new().with(Color::Green).bold(),
+        prompt: ContentStyle::new().with(Color::White).bold(),
+        prompt_char: " λ ".into(),
+    }
+}
+
+impl Style {
+    /// Builds the style for `theme`, dropping all colors when `NO_COLOR` is
+    /// set.
+    pub fn from_theme(theme: &Theme) -> Style {
+        let mut style = match theme.preset {
+            Preset::Dark => builtin(),
+            Preset::Light => light(),
+            Preset::HighContrast => high_contrast(),
+        };
+        for (field, spec) in [
+            (&mut style.heading, &theme.heading),
+            (&mut style.normal, &theme.normal),
+            (&mut style.button, &theme.button),
+            (&mut style.status, &theme.status),
+            (&mut style.directory, &theme.directory),
+            (&mut style.error, &theme.error),
+            (&mut style.command, &theme.command),
+            (&mut style.flag_off, &theme.flag_off),
+            (&mut style.flag_on, &theme.flag_on),
+            (&mut style.prompt, &theme.prompt),
+        ] {
+            if let Some(spec) = spec {
+                *field = spec.0;
+            }
+        }
+        if let Some(prompt_char) = &theme.prompt_char {
+            style.prompt_char = prompt_char.clone();
+        }
+        if std::env::var_os("NO_COLOR").is_some_and(|x| !x.is_empty()) {
+            style.strip_colors();
+        }
+        style
+    }
+
+    fn strip_colors(&mut self) {
+        for field in [
+            &mut self.heading,
+            &mut self.normal,
+            &mut self.button,
+            &mut self.status,
+            &mut self.directory,
+            &mut self.error,
+            &mut self.command,
+            &mut self.flag_off,
+            &mut self.flag_on,
+            &mut self.prompt,
+        ] {
+            field.foreground_color = None;
+            field.background_color = None;
+            field.underline_color = None;
+        }
     }
 }
@@ -1,3 +1,4 @@
+use std::borrow::Cow;
 use std::process::ExitStatus;
 
 use anyhow::bail;
@@ -36,3 +37,130 @@ pub fn exit_status_to_error(exit_code: ExitStatus) -> anyhow::Result<()> {
         bail!("exit code {}", exit_code.code().unwrap_or(-1));
     }
 }
+
+/// Whether `value` is a single word to every shell, without quotes.
+pub fn is_plain_word(value: &str) -> bool {
+    let safe = |b: u8| b.is_ascii_alphanumeric() || b"-_./=:,+@%".contains(&b);
+    !value.is_empty() && value.bytes().all(safe)
+}
+
+pub fn shell_quote(value: &str) -> Cow<'_, str> {
+    if is_plain_word(value) {
+        Cow::Borrowed(value)
+    } else {
+        Cow::Owned(format!("'{}'", value.replace('\'', r"'\''")))
+    }
+}
+
+/// Like [`shell_quote`] for fish, where backslashes escape in single quotes.
+pub fn fish_quote(value: &str) -> Cow<'_, str> {
+    if is_plain_word(value) {
+        Cow::Borrowed(value)
+    } else {
+        let value = value.replace('\\', r"\\").replace('\'', r"\'");
+        Cow::Owned(format!("'{value}'"))
+    }
+}
+
+/// Splits `command` into words like a shell would, handling quotes and
+/// backslashes but nothing else. `None` when a quote is not closed.
+pub fn shell_split(command: &str) -> Option<Vec<String>> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut chars = command.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => words.extend(word.take()),
+            '\\' => word.get_or_insert_with(String::new).push(chars.next()?),
+            '\'' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next()? {
+                        '\'' => break,
+                        c => word.push(c),
+                    }
+                }
+            }
+            '"' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next()? {
+                        '"' => break,
+                        '\\' => match chars.next()? {
+                            c @ ('"' | '\\' | '$' | '`') => word.push(c),
+                            c => word.extend(['\\', c]),
+                        },
+                        c => word.push(c),
+                    }
+                }
+            }
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    words.extend(word);
+    Some(words)
+}
+
+/// Standard base64 with padding.
+pub fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shell_quote_leaves_safe_words() {
+        assert_eq!(shell_quote("--branch=main"), "--branch=main");
+        assert_eq!(shell_quote("a b"), "'a b'");
+        assert_eq!(shell_quote(""), "''");
+        assert_eq!(shell_quote("it's"), r"'it'\''s'");
+    }
+
+    #[test]
+    fn fish_quote_escapes_backslashes() {
+        assert_eq!(fish_quote("--branch=main"), "--branch=main");
+        assert_eq!(fish_quote(r"it's a\b"), r"'it\'s a\\b'");
+    }
+
+    #[test]
+    fn shell_split_handles_quotes() {
+        assert_eq!(
+            shell_split(r#"code --wait 'a b' "c \"d\"" e\ f"#),
+            Some(vec![
+                "code".to_owned(),
+                "--wait".to_owned(),
+                "a b".to_owned(),
+                r#"c "d""#.to_owned(),
+                "e f".to_owned(),
+            ])
+        );
+        assert_eq!(shell_split("''"), Some(vec![String::new()]));
+        assert_eq!(shell_split("'open"), None);
+    }
+
+    #[test]
+    fn base64_pads() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(base64(b"foobar"), "Zm9vYmFy");
+    }
+}
@@ -0,0 +1,40 @@
+use std::time::Duration;
+
+use tokio::task::JoinHandle;
+
+use crate::multi_term;
+use crate::ui::ExternalContext;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Polls the windows of the multiplexer and sends them to the ui when they
+/// change.
+#[derive(Debug)]
+pub struct WindowWatcher {
+    join_handle: JoinHandle<()>,
+}
+
+impl WindowWatcher {
+    pub fn new(ctx: ExternalContext) -> Self {
+        let join_handle = tokio::spawn(async move {
+            let mut last = None;
+            loop {
+                // a failed poll keeps the last windows
+                if let Ok(windows) = multi_term::poll_windows().await {
+                    if last.as_ref() != Some(&windows) {
+                        last = Some(windows.clone());
+                        ctx.update_windows(windows).await;
+                    }
+                }
+                tokio::time::sleep(POLL_INTERVAL).await;
+            }
+        });
+        Self { join_handle }
+    }
+}
+
+impl Drop for WindowWatcher {
+    fn drop(&mut self) {
+        self.join_handle.abort()
+    }
+}